@@ -6,29 +6,192 @@ use ic_cdk::api::time;
 use ic_cdk::caller;
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
 use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable};
+use std::ops::Bound;
 use std::{borrow::Cow, cell::RefCell};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = Cell<u64, Memory>;
+type TextCell = Cell<String, Memory>;
+type IdIndex = StableBTreeMap<StorableString, IdList, Memory>;
+// the keys of a secondary index whose bucket was full when a shoe had to be filed under them
+type OverflowKeys = StableBTreeMap<StorableString, (), Memory>;
+
+// a point in time in nanoseconds since the epoch, as returned by `time()`. encoded as a bare nat64,
+// so it reads and writes the same as the plain u64 timestamps stored before it
+#[derive(
+    candid::CandidType,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+)]
+struct Timestamp(u64);
+
+impl Timestamp {
+    fn now() -> Self {
+        Timestamp(time())
+    }
+
+    fn as_nanos(self) -> u64 {
+        self.0
+    }
+
+    fn as_millis(self) -> u64 {
+        self.0 / 1_000_000
+    }
+
+    fn as_secs(self) -> u64 {
+        self.0 / 1_000_000_000
+    }
+}
 
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct Shoe {
     owner: String,
     id: u64,
     name: String,
+    // stored trimmed and uppercased; None for shoes stored before SKUs existed. None and an
+    // empty SKU both mean the shoe has no SKU
+    sku: Option<String>,
+    // stored trimmed and lowercased; None for shoes stored before categories existed, read as
+    // no category
+    category: Option<String>,
+    // stored trimmed and lowercased; None when the shoe has no brand
+    brand: Option<String>,
     size: String,
+    shoe_size: Option<ShoeSize>,
     shoe_url: String,
+    image_hash: Option<String>,
     price: i16,
+    sale_price: Option<i16>,
     quantity: i16,
+    low_stock_threshold: Option<i16>,
+    restock_date: Option<u64>,
+    // lifetime units sold. optional so shoes stored before it was added still decode; None reads as 0
+    sold: Option<u64>,
+    // None only for shoes stored before conditions existed, until post_upgrade fills it in
+    condition: Option<ShoeCondition>,
+    // false while the seller stages a draft; None for shoes stored before drafts existed, read as published
+    published: Option<bool>,
+    // when the shoe last went live; None for drafts never published and shoes stored before this was recorded
+    published_at: Option<u64>,
     like: u32,
-    liked_by: Vec<Principal>,
+    created_at: Timestamp,
+    updated_at: Option<Timestamp>,
+    deleted_at: Option<u64>,
+}
+
+// one action a principal took, recorded in the audit log
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct AuditEntry {
+    id: u64,
+    actor: Principal,
+    action: String,
+    shoe_id: Option<u64>,
+    timestamp: u64,
+}
+
+impl Storable for AuditEntry {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for AuditEntry {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// the admin-configurable rules checked before a shoe is added or liked. every rule starts off
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Policy {
+    // sellers can't like their own shoes
+    no_self_like: bool,
+    // the anonymous principal can't add or like shoes
+    no_anonymous: bool,
+    // the most shoes a principal can add or like per minute, unlimited when None
+    max_actions_per_minute: Option<u32>,
+    // principals on the blocklist can't add or like shoes
+    blocklist_enabled: bool,
+}
+
+impl Storable for Policy {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// the actions the policy rules apply to
+enum PolicyAction<'a> {
+    AddShoe,
+    Like(&'a Shoe),
+}
+
+// the likes of a shoe as stored before they moved out to SHOE_LIKES. only read while upgrading
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct LegacyShoeLikes {
+    id: u64,
     created_at: u64,
-    updated_at: Option<u64>,
+    liked_by: Option<Vec<Principal>>,
+}
+
+impl Storable for LegacyShoeLikes {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for LegacyShoeLikes {
+    const MAX_SIZE: u32 = Shoe::MAX_SIZE;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// the condition a shoe is sold in
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Debug, Default)]
+enum ShoeCondition {
+    #[default]
+    New,
+    LikeNew,
+    Used,
+    Refurbished,
+}
+
+// sizing systems a shoe size can be expressed in. the discriminants are the SIZE_CHART columns
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+enum SizeSystem {
+    US = 0,
+    UK = 1,
+    EU = 2,
+}
+
+// a structured shoe size parsed from the free-form size text, e.g. "US 10.5" or "44 EU"
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+struct ShoeSize {
+    value: f32,
+    system: SizeSystem,
 }
 
 // a trait that must be implemented for a struct that is stored in a stable struct
 impl Storable for Shoe {
-    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
 
@@ -43,226 +206,8189 @@ impl BoundedStorable for Shoe {
     const IS_FIXED_SIZE: bool = false;
 }
 
-thread_local! {
-    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
-        MemoryManager::init(DefaultMemoryImpl::default())
-    );
-
-    static ID_COUNTER: RefCell<IdCell> = RefCell::new(
-        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
-            .expect("Cannot create a counter")
-    );
-
-    static SHOE_STORAGE: RefCell<StableBTreeMap<u64, Shoe, Memory>> =
-        RefCell::new(StableBTreeMap::init(
-            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
-    ));
+// a record of a completed purchase_shoe call
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Purchase {
+    id: u64,
+    buyer: Principal,
+    shoe_id: u64,
+    amount: i16,
+    unit_price: i16,
+    total: u64,
+    timestamp: u64,
 }
 
-// Shoe payload for adding or updating an Shoes
-#[derive(candid::CandidType, Serialize, Deserialize, Default)]
-struct ShoePayload {
-    name: String,
-    size: String,
-    shoe_url: String,
-    price: i16,
-    quantity: i16,
+impl Storable for Purchase {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
 }
 
-fn is_valid_price(price: i16) -> bool {
-    price > 0 // Assuming the price can't be negative
+impl BoundedStorable for Purchase {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
 }
 
-fn is_valid_quantity(quantity: i16) -> bool {
-    quantity > 0 // Assuming at least 1 item must be present
+// a seller's public profile
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct SellerProfile {
+    display_name: String,
+    return_policy: String,
 }
 
-// validate shoe payload for price and quantity
-fn validate_shoe_payload(payload: &ShoePayload) -> Result<(), String> {
-    if !is_valid_price(payload.price) {
-        return Err("Invalid price value".into());
+impl Storable for SellerProfile {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
     }
-    if !is_valid_quantity(payload.quantity) {
-        return Err("Invalid quantity value".into());
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
     }
-    Ok(())
 }
 
-// Function that add new shoes to the store
-#[ic_cdk::update]
-fn add_shoe(shoe_payload: ShoePayload) -> Result<Shoe, String> {
-    validate_shoe_payload(&shoe_payload)?;
+impl BoundedStorable for SellerProfile {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
 
-    let liked_by: Vec<Principal> = Vec::new(); // Initializes an empty Vec for the liked field
-    let id = ID_COUNTER
-        .with(|counter| {
-            let current_value = *counter.borrow().get();
-            counter.borrow_mut().set(current_value + 1)
-        })
-        .expect("cannot increment id counter");
+// a Q&A comment left on a shoe
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Comment {
+    id: u64,
+    shoe_id: u64,
+    author: Principal,
+    text: String,
+    created_at: u64,
+}
 
-    let shoe = Shoe {
-        owner: caller().to_string(),
-        id,
-        name: shoe_payload.name,
-        size: shoe_payload.size,
-        shoe_url: shoe_payload.shoe_url,
-        price: shoe_payload.price,
-        quantity: shoe_payload.quantity,
-        like: 0,
-        liked_by,
-        created_at: time(),
-        updated_at: None,
-    };
+impl Storable for Comment {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
 
-    do_insert(&shoe);
-    Ok(shoe)
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
 }
 
-// get all the available shoes in the store
-#[ic_cdk::query]
-fn get_shoes() -> Vec<Shoe> {
-    SHOE_STORAGE.with(|service| {
-        let storage = service.borrow_mut();
-        storage.iter().map(|(_, item)| item.clone()).collect()
-    })
+impl BoundedStorable for Comment {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
 }
 
-// function to retrieve details of a specific Shoe by the shoe id
-#[ic_cdk::query]
-fn get_shoe_by_id(id: u64) -> Result<Shoe, Error> {
-    match _get_shoe(&id) {
-        Some(shoe) => Ok(shoe),
-        None => Err(Error::NotFound {
-            msg: format!("a shoe with id={} not found", id),
-        }),
+// a rating left on a shoe, one per reviewer
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Review {
+    shoe_id: u64,
+    author: Principal,
+    rating: u8,
+    text: String,
+    created_at: u64,
+}
+
+impl Storable for Review {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
     }
 }
 
-// Get Total number of shoes available in the store
-#[ic_cdk::query]
-fn total_number_of_shoes() -> i16 {
-    SHOE_STORAGE.with(|service| {
-        let storage = service.borrow_mut();
-        storage.iter().map(|(_, item)| item.quantity).sum()
-    })
+impl BoundedStorable for Review {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
 }
 
-// Function that modify the details of a shoe
-#[ic_cdk::update]
-fn update_shoe(id: u64, payload: ShoePayload) -> Result<Shoe, Error> {
-    let shoe = _get_shoe(&id).expect("Shoe not found");
-    // Check if the caller is the owner of the shoe; if not, return an authorization error
-    if !_validate_owner(&shoe.clone()) {
-        return Err(Error::NotAuthorized {
-            msg: format!("You're not the owner of the shoe with id={}", id),
-            caller: caller(),
-        });
+// units of a shoe held for a buyer until `expires_at`
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct Reservation {
+    id: u64,
+    shoe_id: u64,
+    buyer: Principal,
+    amount: i16,
+    expires_at: u64,
+}
+
+impl Storable for Reservation {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
     }
-    match SHOE_STORAGE.with(|service| service.borrow().get(&id)) {
-        Some(mut shoe) => {
-            shoe.name = payload.name;
-            shoe.size = payload.size;
-            shoe.price = payload.price;
-            shoe.shoe_url = payload.shoe_url;
-            shoe.quantity = payload.quantity;
-            shoe.updated_at = Some(time());
-            do_insert(&shoe);
-            Ok(shoe)
-        }
-        None => Err(Error::NotFound {
-            msg: format!("couldn't update a shoe with id={}. shoe not found", id),
-        }),
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
     }
 }
 
-// Search Shoe Items by Name
-#[ic_cdk::query]
-fn search_by_name(name: String) -> Vec<Shoe> {
-    SHOE_STORAGE.with(|service| {
-        let storage = service.borrow_mut();
-        storage
-            .iter()
-            .filter(|(_, item)| item.name == name)
-            .map(|(_, item)| item.clone())
-            .collect()
-    })
+impl BoundedStorable for Reservation {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
 }
 
-// Function that likes a shoe by its id
-#[ic_cdk::update]
-fn like_shoe(id: u64) -> Result<Shoe, Error> {
-    match _get_shoe(&id) {
-        Some(mut likes_shoe) => {
-            let caller = caller();
-            // Search for the index of the caller in the liked array
-            let index = likes_shoe
-                .liked_by
-                .iter()
-                .position(|&user| user.to_string() == caller.to_string());
-            // // if an index is returned, return an error as users can only like once
-            if index.is_some() {
-                return Err(Error::AlreadyLiked {
-                    msg: format!(
-                        "Shoe with ID {} has already been liked by caller: {}.",
-                        id, caller
-                    ),
-                });
-            }
-            likes_shoe.like += 1;
-            likes_shoe.liked_by.push(caller);
-            do_insert(&likes_shoe);
-            Ok(likes_shoe.clone())
-        }
-        None => Err(Error::NotFound {
-            msg: format!("Shoe with ID {} not found. Cannot like.", id),
-        }),
-    }
+// a single recorded change to one field of a shoe
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+struct HistoryEntry {
+    field: String,
+    old_value: String,
+    new_value: String,
+    timestamp: u64,
 }
 
-// Update function to delete a specific shoe by its id
-#[ic_cdk::update]
-fn delete_shoe(id: u64) -> Result<Shoe, Error> {
-    let shoe = _get_shoe(&id).expect("Shoe not found");
-    // Check if the caller is the owner of the shoe; if not, return an authorization error
-    if !_validate_owner(&shoe.clone()) {
-        return Err(Error::NotAuthorized {
-            msg: format!("You're not the owner of the event with id={}", id),
-            caller: caller(),
-        });
+// the bounded change trail of a shoe, oldest entry first
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct ShoeHistory(Vec<HistoryEntry>);
+
+impl Storable for ShoeHistory {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
     }
-    // Attempt to remove the shoe from storage based on its unique identifier
-    match SHOE_STORAGE.with(|service| service.borrow_mut().remove(&id)) {
-        Some(shoe) => Ok(shoe),
-        None => Err(Error::NotFound {
-            msg: format!("couldn't delete a shoe with id={}. shoe not found.", id),
-        }),
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
     }
 }
 
-#[derive(candid::CandidType, Deserialize, Serialize)]
-enum Error {
-    NotFound { msg: String },
-    NotAuthorized { msg: String, caller: Principal },
-    AlreadyLiked { msg: String },
+// MAX_HISTORY_LEN entries with values of at most MAX_HISTORY_VALUE_LEN characters
+impl BoundedStorable for ShoeHistory {
+    const MAX_SIZE: u32 = 24_576;
+    const IS_FIXED_SIZE: bool = false;
 }
 
-// helper method to perform insert.
-fn do_insert(shoe: &Shoe) {
-    SHOE_STORAGE.with(|service| service.borrow_mut().insert(shoe.id, shoe.clone()));
-}
+// the ids of the shoes filed under one key of a secondary index
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct IdList(Vec<u64>);
 
-// a helper method to get a message by id. used in get_message/update_message
-fn _get_shoe(id: &u64) -> Option<Shoe> {
-    SHOE_STORAGE.with(|service| service.borrow().get(id))
-}
-// Helper function to validate owner
-fn _validate_owner(shoe: &Shoe) -> bool {
-    if shoe.owner.to_string() != caller().to_string() {
-        return false;
+impl Storable for IdList {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
     }
-    return true;
+}
+
+// MAX_IDS_PER_INDEX_KEY ids of 8 bytes each plus the encoding header
+impl BoundedStorable for IdList {
+    const MAX_SIZE: u32 = 32_768;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// standard men's size chart used to convert sizes, one row per size as [US, UK, EU]
+const SIZE_CHART: [[f32; 3]; 17] = [
+    [6.0, 5.5, 38.5],
+    [6.5, 6.0, 39.0],
+    [7.0, 6.5, 40.0],
+    [7.5, 7.0, 40.5],
+    [8.0, 7.5, 41.0],
+    [8.5, 8.0, 42.0],
+    [9.0, 8.5, 42.5],
+    [9.5, 9.0, 43.0],
+    [10.0, 9.5, 44.0],
+    [10.5, 10.0, 44.5],
+    [11.0, 10.5, 45.0],
+    [11.5, 11.0, 45.5],
+    [12.0, 11.5, 46.0],
+    [12.5, 12.0, 47.0],
+    [13.0, 12.5, 47.5],
+    [14.0, 13.5, 48.5],
+    [15.0, 14.5, 49.5],
+];
+
+// the image urls of a shoe, kept out of Shoe so they don't count against Shoe::MAX_SIZE
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct ImageList(Vec<String>);
+
+impl Storable for ImageList {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// MAX_IMAGES urls of at most MAX_IMAGE_URL_LEN bytes plus the encoding header
+impl BoundedStorable for ImageList {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// the tags of a shoe, kept out of Shoe so they don't count against Shoe::MAX_SIZE
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct TagList(Vec<String>);
+
+impl Storable for TagList {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// MAX_TAGS tags of at most MAX_TAG_LEN characters of up to 4 bytes plus the encoding header
+impl BoundedStorable for TagList {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// a shoe's own return policy, overriding its seller's. kept out of Shoe so it doesn't count
+// against Shoe::MAX_SIZE
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct ReturnPolicy(String);
+
+impl Storable for ReturnPolicy {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// MAX_RETURN_POLICY_LEN bytes plus the encoding header
+impl BoundedStorable for ReturnPolicy {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// a principal wrapper so principals can be used as keys of a stable map
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct StorablePrincipal(Principal);
+
+impl Storable for StorablePrincipal {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_slice())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        StorablePrincipal(Principal::from_slice(bytes.as_ref()))
+    }
+}
+
+// the management canister's empty principal sorts before every other principal
+impl Default for StorablePrincipal {
+    fn default() -> Self {
+        StorablePrincipal(Principal::management_canister())
+    }
+}
+
+impl BoundedStorable for StorablePrincipal {
+    const MAX_SIZE: u32 = 29;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// the exponents of the trending score `like^like_weight / (age_in_seconds + 1)^recency_weight`
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize)]
+struct TrendingWeights {
+    like_weight: f64,
+    recency_weight: f64,
+}
+
+impl Default for TrendingWeights {
+    fn default() -> Self {
+        TrendingWeights {
+            like_weight: 1.0,
+            recency_weight: 1.0,
+        }
+    }
+}
+
+impl Storable for TrendingWeights {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// the trending ranking computed by the heartbeat, most trending id first
+struct TrendingCache {
+    computed_at: u64,
+    ids: Vec<u64>,
+}
+
+// a short string wrapper so strings such as SKUs can be used as keys of a stable map
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct StorableString(String);
+
+impl Storable for StorableString {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Borrowed(self.0.as_bytes())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        StorableString(String::from_utf8(bytes.into_owned()).unwrap())
+    }
+}
+
+impl BoundedStorable for StorableString {
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
+        MemoryManager::init(DefaultMemoryImpl::default())
+    );
+
+    static ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(0))), 0)
+            .expect("Cannot create a counter")
+    );
+
+    static SHOE_STORAGE: RefCell<StableBTreeMap<u64, Shoe, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
+    ));
+
+    static STORE_CURRENCY: RefCell<TextCell> = RefCell::new(
+        TextCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))), DEFAULT_CURRENCY.to_string())
+            .expect("Cannot create the store currency cell")
+    );
+
+    static LIKE_LIMIT: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))), DEFAULT_LIKE_LIMIT)
+            .expect("Cannot create the like limit cell")
+    );
+
+    static MIN_PRICE: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))), 0)
+            .expect("Cannot create the minimum price cell")
+    );
+
+    static LIKES_GIVEN: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+    ));
+
+    static PURCHASE_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))), 0)
+            .expect("Cannot create a purchase counter")
+    );
+
+    static PURCHASE_STORAGE: RefCell<StableBTreeMap<u64, Purchase, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+    ));
+
+    static HISTORY_STORAGE: RefCell<StableBTreeMap<u64, ShoeHistory, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8)))
+    ));
+
+    static VERIFIED_SELLERS: RefCell<StableBTreeMap<StorablePrincipal, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+    ));
+
+    // reports keyed by (shoe id, reporter) with the time of the report
+    static SHOE_REPORTS: RefCell<StableBTreeMap<(u64, StorablePrincipal), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+    ));
+
+    static MAX_QUANTITY: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13))), i16::MAX as u64)
+            .expect("Cannot create the maximum quantity cell")
+    );
+
+    static COMMENT_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11))), 0)
+            .expect("Cannot create a comment counter")
+    );
+
+    static COMMENT_STORAGE: RefCell<StableBTreeMap<u64, Comment, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12)))
+    ));
+
+    static SELLER_PROFILES: RefCell<StableBTreeMap<StorablePrincipal, SellerProfile, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+    ));
+
+    static SKU_INDEX: RefCell<StableBTreeMap<StorableString, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15)))
+    ));
+
+    static FEATURED_SHOES: RefCell<StableBTreeMap<u64, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16)))
+    ));
+
+    static FEATURED_ROTATION_INTERVAL: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17))), DEFAULT_FEATURED_ROTATION_SECS)
+            .expect("Cannot create the featured rotation interval cell")
+    );
+
+    // the balance each seller has accrued from purchases of their shoes
+    static LEDGER: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18)))
+    ));
+
+    static RESERVATION_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19))), 0)
+            .expect("Cannot create a reservation counter")
+    );
+
+    static RESERVATION_STORAGE: RefCell<StableBTreeMap<u64, Reservation, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20)))
+    ));
+
+    static DEFAULT_SORT: RefCell<Cell<SortBy, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21))), SortBy::CreatedAt)
+            .expect("Cannot create the default sort cell")
+    );
+
+    static REVIEW_STORAGE: RefCell<StableBTreeMap<(u64, StorablePrincipal), Review, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22)))
+    ));
+
+    static NAME_INDEX: RefCell<IdIndex> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23)))
+    ));
+
+    static SIZE_INDEX: RefCell<IdIndex> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24)))
+    ));
+
+    static WISHLISTS: RefCell<StableBTreeMap<StorablePrincipal, IdList, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25)))
+    ));
+
+    static ALLOWED_CATEGORIES: RefCell<StableBTreeMap<StorableString, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26)))
+    ));
+
+    static SHOE_LIKES: RefCell<StableBTreeMap<(u64, StorablePrincipal), u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(27)))
+    ));
+
+    static POLICY: RefCell<Cell<Policy, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(28))), Policy::default())
+            .expect("Cannot create the policy cell")
+    );
+
+    static BLOCKLIST: RefCell<StableBTreeMap<StorablePrincipal, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(29)))
+    ));
+
+    // the start of each principal's current rate limit window and its actions within it.
+    // kept on the heap, so the windows restart on upgrade
+    static RATE_LIMITS: RefCell<std::collections::BTreeMap<Principal, (u64, u32)>> =
+        const { RefCell::new(std::collections::BTreeMap::new()) };
+
+    static TRENDING_WEIGHTS: RefCell<Cell<TrendingWeights, Memory>> = RefCell::new(
+        Cell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(30))), TrendingWeights::default())
+            .expect("Cannot create the trending weights cell")
+    );
+
+    // 1 while the store is open, 0 while an admin has closed it
+    static STORE_OPEN: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(31))), 1)
+            .expect("Cannot create the store open cell")
+    );
+
+    static BRAND_INDEX: RefCell<IdIndex> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(32)))
+    ));
+
+    static LIKE_COOLDOWN: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(33))), 0)
+            .expect("Cannot create the like cooldown cell")
+    );
+
+    static LAST_LIKE_AT: RefCell<StableBTreeMap<StorablePrincipal, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(34)))
+    ));
+
+    static MAX_PAGE_SIZE: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(35))), DEFAULT_MAX_PAGE_SIZE)
+            .expect("Cannot create the max page size cell")
+    );
+
+    static AUDIT_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(36))), 0)
+            .expect("Cannot create an audit counter")
+    );
+
+    static AUDIT_LOG: RefCell<StableBTreeMap<u64, AuditEntry, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(37)))
+    ));
+
+    static SHOE_IMAGES: RefCell<StableBTreeMap<u64, ImageList, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(38)))
+    ));
+
+    static SHOE_RETURN_POLICIES: RefCell<StableBTreeMap<u64, ReturnPolicy, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(39)))
+    ));
+
+    static NAME_INDEX_OVERFLOW: RefCell<OverflowKeys> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(40)))
+    ));
+
+    static SIZE_INDEX_OVERFLOW: RefCell<OverflowKeys> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(41)))
+    ));
+
+    static BRAND_INDEX_OVERFLOW: RefCell<OverflowKeys> = RefCell::new(StableBTreeMap::init(
+        MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(42)))
+    ));
+
+    static SHOE_TAGS: RefCell<StableBTreeMap<u64, TagList, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(43)))
+    ));
+
+    // heap-only, so the cache starts empty after an upgrade until the next heartbeat
+    static TRENDING_CACHE: RefCell<Option<TrendingCache>> = const { RefCell::new(None) };
+}
+
+const DEFAULT_CURRENCY: &str = "USD";
+const MAX_CURRENCY_LEN: usize = 10;
+// likes are kept outside the shoe, so nothing bounds them until an admin sets a limit
+const DEFAULT_LIKE_LIMIT: u64 = u64::MAX;
+const MAX_OWNERS_PER_QUERY: usize = 50;
+const DEFAULT_MAX_PAGE_SIZE: u64 = 100;
+const MAX_IMAGES: usize = 6;
+// categories whose items are not sized, every other category requires a size
+const SIZELESS_CATEGORIES: [&str; 2] = ["accessory", "accessories"];
+const TRENDING_CACHE_SIZE: u64 = 50;
+// in bytes, keeps a comment within Comment::MAX_SIZE
+const MAX_COMMENT_LEN: usize = 500;
+const MAX_IDS_PER_INDEX_KEY: usize = 4_000;
+const MAX_WISHLIST_LEN: usize = 200;
+const RATE_LIMIT_WINDOW: u64 = 60 * 1_000_000_000;
+// version 1 is the original ShoePayload (name, size, shoe_url, price and quantity). version 2
+// added the sku, category, brand, images, image_hash and condition fields, and version 3 the
+// published flag, all optional
+const CURRENT_SCHEMA_VERSION: u16 = 3;
+const MAX_TAGS: usize = 10;
+const MAX_TAG_LEN: usize = 30;
+const MAX_AUDIT_LOG_LEN: u64 = 10_000;
+const MIN_RATING: u8 = 1;
+const MAX_RATING: u8 = 5;
+// one hour
+const DEFAULT_FEATURED_ROTATION_SECS: u64 = 3_600;
+// fifteen minutes in nanoseconds
+const RESERVATION_TTL: u64 = 15 * 60 * 1_000_000_000;
+const RESERVATION_CLEANUP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+// in bytes, keep a profile within SellerProfile::MAX_SIZE
+const MAX_DISPLAY_NAME_LEN: usize = 50;
+const MAX_RETURN_POLICY_LEN: usize = 500;
+// one minute in nanoseconds
+const TRENDING_REFRESH_INTERVAL: u64 = 60_000_000_000;
+const MAX_IMAGE_URL_LEN: usize = 300;
+const MAX_HISTORY_LEN: usize = 20;
+const MAX_HISTORY_VALUE_LEN: usize = 100;
+
+// Shoe payload for adding or updating an Shoes
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct ShoePayload {
+    // the payload layout the client was built against, 1 when not given
+    schema_version: Option<u16>,
+    name: String,
+    // the fields below that are optional were added after version 1. when not given, add_shoe
+    // uses no value (New for the condition) and update_shoe keeps the current one
+    sku: Option<String>,
+    category: Option<String>,
+    brand: Option<String>,
+    size: String,
+    shoe_url: String,
+    images: Option<Vec<String>>,
+    image_hash: Option<String>,
+    price: i16,
+    quantity: i16,
+    condition: Option<ShoeCondition>,
+    // added in version 3. false stages the shoe as a draft
+    published: Option<bool>,
+}
+
+// Shoe update for patching only the provided fields of a shoe
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct ShoeUpdate {
+    name: Option<String>,
+    // an empty brand clears it
+    brand: Option<String>,
+    category: Option<String>,
+    size: Option<String>,
+    price: Option<i16>,
+    shoe_url: Option<String>,
+    image_hash: Option<String>,
+    quantity: Option<i16>,
+}
+
+// a shoe as shown in a listing, with details derived from the rest of the store
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct ShoeListing {
+    shoe: Shoe,
+    verified: bool,
+    discount_percent: Option<u8>,
+}
+
+// a shoe with the ids of the active shoes before and after it, for prev/next navigation
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct ShoeNav {
+    prev: Option<u64>,
+    current: Shoe,
+    next: Option<u64>,
+}
+
+// the filters of search_and_sort; filters left as None match every shoe
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct ShoeFilter {
+    name: Option<String>,
+    category: Option<String>,
+    min_price: Option<i16>,
+    max_price: Option<i16>,
+    in_stock: Option<bool>,
+}
+
+// the keys shoes can be sorted by
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+enum SortBy {
+    Price,
+    Likes,
+    CreatedAt,
+    Name,
+}
+
+impl Storable for SortBy {
+    fn to_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+// the fields of a shoe a listing grid needs
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct ShoeSummary {
+    id: u64,
+    name: String,
+    category: String,
+    price: i16,
+    sale_price: Option<i16>,
+    shoe_url: String,
+    like: u32,
+}
+
+// one size variant of a product in size_matrix
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct SizeAvailability {
+    size: String,
+    shoe_id: u64,
+    quantity: i16,
+    price: i16,
+}
+
+// a shoe summary along with its review stats
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct ShoeWithRating {
+    shoe: ShoeSummary,
+    average_rating: f64,
+    review_count: u64,
+}
+
+// a page of shoes along with the number of shoes matching the query
+#[derive(candid::CandidType, Serialize, Deserialize)]
+struct PagedShoes {
+    shoes: Vec<Shoe>,
+    total: u64,
+    offset: u64,
+    limit: u64,
+}
+
+// the state of a shoe, derived from its fields: a soft-deleted shoe is Deleted, an unpublished
+// one is a Draft, otherwise it is OutOfStock when none are left and Active when some are
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+enum ShoeStatus {
+    Active,
+    Deleted,
+    OutOfStock,
+    Draft,
+}
+
+// the fields get_shoe_field can project a shoe to
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
+enum ShoeField {
+    Owner,
+    Name,
+    Sku,
+    Category,
+    Size,
+    ShoeUrl,
+    Price,
+    SalePrice,
+    Quantity,
+    Like,
+    CreatedAt,
+    UpdatedAt,
+}
+
+// aggregate figures over the caller's shoes returned by my_dashboard
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct SellerDashboard {
+    listing_count: u64,
+    total_units: u64,
+    total_likes: u64,
+    out_of_stock: u64,
+    inventory_value: u64,
+}
+
+// store-wide stock figures at `taken_at`, returned by inventory_snapshot
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct InventorySnapshot {
+    taken_at: u64,
+    total_units: u64,
+    total_value: u64,
+    listings: u64,
+}
+
+fn is_valid_price(price: i16) -> bool {
+    price > 0 // Assuming the price can't be negative
+}
+
+fn is_valid_quantity(quantity: i16) -> bool {
+    quantity > 0 // Assuming at least 1 item must be present
+}
+
+// categories are compared case-insensitively, so they are stored trimmed and lowercased
+fn normalize_category(category: &str) -> String {
+    category.trim().to_lowercase()
+}
+
+// sizes are canonicalized as a lowercase system label followed by the number, e.g. "us 10", or
+// just the number when no system is given, so "US 10.0", "10 US" and "us10" collapse together
+fn normalize_size(size: &str) -> String {
+    if let Some(shoe_size) = parse_shoe_size(size) {
+        let label = match shoe_size.system {
+            SizeSystem::US => "us",
+            SizeSystem::UK => "uk",
+            SizeSystem::EU => "eu",
+        };
+        return format!("{} {}", label, shoe_size.value);
+    }
+    let size = size
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    match size.parse::<f32>() {
+        Ok(value) if value.is_finite() && value > 0.0 => value.to_string(),
+        _ => size,
+    }
+}
+
+// brands are compared case-insensitively, so like categories they are stored trimmed and
+// lowercased. a blank brand means the shoe has no brand
+fn normalize_brand(brand: &str) -> Option<String> {
+    let brand = brand.trim().to_lowercase();
+    (!brand.is_empty()).then_some(brand)
+}
+
+// SKUs are unique regardless of case and surrounding whitespace, so they are stored trimmed and uppercased
+fn normalize_sku(sku: &str) -> String {
+    sku.trim().to_uppercase()
+}
+
+// parse a size such as "US 10.5", "eu44" or "9 UK" into a structured size.
+// sizes without a recognised system or a positive value are left unstructured
+fn parse_shoe_size(size: &str) -> Option<ShoeSize> {
+    let normalized: String = size
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    [
+        ("US", SizeSystem::US),
+        ("EU", SizeSystem::EU),
+        ("UK", SizeSystem::UK),
+    ]
+    .iter()
+    .find_map(|(label, system)| {
+        let value = normalized
+            .strip_prefix(label)
+            .or_else(|| normalized.strip_suffix(label))?;
+        match value.parse::<f32>() {
+            Ok(value) if value.is_finite() && value > 0.0 => Some(ShoeSize {
+                value,
+                system: *system,
+            }),
+            _ => None,
+        }
+    })
+}
+
+// validate a price against the store's minimum price floor
+fn validate_min_price(price: i16) -> Result<(), Error> {
+    let min_price = get_min_price();
+    if price < min_price {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "Price {} is below the minimum price of {}",
+                price, min_price
+            ),
+        });
+    }
+    Ok(())
+}
+
+// validate that a size is given unless the category doesn't need one, e.g. accessories
+fn validate_size_for_category(category: &str, size: &str) -> Result<(), Error> {
+    let category = normalize_category(category);
+    if size.trim().is_empty() && !SIZELESS_CATEGORIES.contains(&category.as_str()) {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A size is required for shoes in the '{}' category",
+                category
+            ),
+        });
+    }
+    Ok(())
+}
+
+// validate that a category is on the allowlist. an empty allowlist allows every category
+fn validate_category(category: &str) -> Result<(), Error> {
+    let category = normalize_category(category);
+    let allowed = ALLOWED_CATEGORIES.with(|service| {
+        let allowlist = service.borrow();
+        allowlist.is_empty() || allowlist.contains_key(&StorableString(category.clone()))
+    });
+    if !allowed {
+        return Err(Error::ValidationError {
+            msg: format!("The category '{}' is not allowed", category),
+        });
+    }
+    Ok(())
+}
+
+// validate that a brand fits the brand index
+fn validate_brand(brand: &str) -> Result<(), Error> {
+    if normalize_brand(brand).is_some_and(|brand| brand.len() > StorableString::MAX_SIZE as usize) {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A brand can be at most {} bytes long",
+                StorableString::MAX_SIZE
+            ),
+        });
+    }
+    Ok(())
+}
+
+// normalize a shoe's tags, dropping blanks and duplicates, and validate their number and length
+fn validate_tags(tags: &[String]) -> Result<Vec<String>, Error> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.chars().count() > MAX_TAG_LEN {
+            return Err(Error::ValidationError {
+                msg: format!("A tag can be at most {} characters long", MAX_TAG_LEN),
+            });
+        }
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_TAGS {
+        return Err(Error::ValidationError {
+            msg: format!("A shoe can have at most {} tags", MAX_TAGS),
+        });
+    }
+    Ok(normalized)
+}
+
+// validate that a SKU fits the SKU index and isn't used by a shoe other than `id`.
+// an empty SKU means the shoe has no SKU
+fn validate_sku(sku: &str, id: Option<u64>) -> Result<(), Error> {
+    let sku = normalize_sku(sku);
+    if sku.len() > StorableString::MAX_SIZE as usize {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A SKU can be at most {} bytes long",
+                StorableString::MAX_SIZE
+            ),
+        });
+    }
+    let existing = SKU_INDEX.with(|service| service.borrow().get(&StorableString(sku.clone())));
+    match existing {
+        Some(existing) if !sku.is_empty() && Some(existing) != id => Err(Error::DuplicateSku {
+            msg: format!(
+                "SKU {} is already used by the shoe with id={}",
+                sku, existing
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+// validate the length of a return policy
+fn validate_return_policy(return_policy: &str) -> Result<(), Error> {
+    if return_policy.len() > MAX_RETURN_POLICY_LEN {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A return policy can be at most {} bytes long",
+                MAX_RETURN_POLICY_LEN
+            ),
+        });
+    }
+    Ok(())
+}
+
+// validate an image url: an http(s) url with a host and without whitespace
+fn is_valid_image_url(url: &str) -> bool {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"));
+    url.len() <= MAX_IMAGE_URL_LEN
+        && !url.chars().any(char::is_whitespace)
+        && rest.is_some_and(|rest| rest.chars().next().is_some_and(|c| !"/$.?#".contains(c)))
+}
+
+// validate the images of a shoe for their urls and count
+fn validate_images(images: &[String]) -> Result<(), Error> {
+    if images.len() > MAX_IMAGES {
+        return Err(Error::ValidationError {
+            msg: format!("A shoe can have at most {} images", MAX_IMAGES),
+        });
+    }
+    if let Some(url) = images.iter().find(|url| !is_valid_image_url(url)) {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid image url '{}'", url),
+        });
+    }
+    Ok(())
+}
+
+// validate a quantity against the store's maximum quantity per listing
+fn validate_max_quantity(quantity: i16) -> Result<(), Error> {
+    let max_quantity = get_max_quantity();
+    if quantity > max_quantity {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "Quantity {} is above the maximum quantity of {}",
+                quantity, max_quantity
+            ),
+        });
+    }
+    Ok(())
+}
+
+// bring a payload from any known schema version up to the current one, defaulting the fields
+// older versions didn't have
+fn negotiate_schema(payload: ShoePayload) -> Result<ShoePayload, Error> {
+    match payload.schema_version.unwrap_or(1) {
+        1 => Ok(ShoePayload {
+            schema_version: Some(CURRENT_SCHEMA_VERSION),
+            sku: None,
+            category: None,
+            brand: None,
+            images: None,
+            image_hash: None,
+            condition: None,
+            published: None,
+            ..payload
+        }),
+        2 => Ok(ShoePayload {
+            schema_version: Some(CURRENT_SCHEMA_VERSION),
+            published: None,
+            ..payload
+        }),
+        CURRENT_SCHEMA_VERSION => Ok(payload),
+        version => Err(Error::UnsupportedSchema {
+            msg: format!(
+                "Unsupported payload schema version {}. the latest is {}",
+                version, CURRENT_SCHEMA_VERSION
+            ),
+            version,
+        }),
+    }
+}
+
+// validate shoe payload for price and quantity
+fn validate_shoe_payload(payload: &ShoePayload) -> Result<(), Error> {
+    if !is_valid_price(payload.price) {
+        return Err(Error::ValidationError {
+            msg: "Invalid price value".into(),
+        });
+    }
+    if !is_valid_quantity(payload.quantity) {
+        return Err(Error::ValidationError {
+            msg: "Invalid quantity value".into(),
+        });
+    }
+    let category = payload.category.as_deref().unwrap_or_default();
+    validate_category(category)?;
+    if let Some(brand) = &payload.brand {
+        validate_brand(brand)?;
+    }
+    validate_size_for_category(category, &payload.size)?;
+    validate_images(payload.images.as_deref().unwrap_or_default())?;
+    validate_max_quantity(payload.quantity)?;
+    validate_min_price(payload.price)
+}
+
+// Function that add new shoes to the store. with `merge_if_exists`, a shoe identical to one
+// the caller already owns (same name, size, price and category) is added to its quantity instead.
+// optional so that callers built before it existed can leave it out
+#[ic_cdk::update]
+fn add_shoe(shoe_payload: ShoePayload, merge_if_exists: Option<bool>) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    _check_policy(PolicyAction::AddShoe, caller(), time())?;
+    let shoe_payload = negotiate_schema(shoe_payload)?;
+    validate_shoe_payload(&shoe_payload)?;
+    if merge_if_exists.unwrap_or(false) {
+        if let Some(existing) = _find_identical_shoe(&shoe_payload, caller()) {
+            return _merge_quantity(existing, shoe_payload.quantity);
+        }
+    }
+    validate_sku(shoe_payload.sku.as_deref().unwrap_or_default(), None)?;
+
+    let id = ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment id counter");
+
+    let published = shoe_payload.published.unwrap_or(true);
+    let shoe = Shoe {
+        owner: caller().to_string(),
+        id,
+        name: shoe_payload.name,
+        sku: shoe_payload.sku.as_deref().map(normalize_sku),
+        category: Some(normalize_category(
+            shoe_payload.category.as_deref().unwrap_or_default(),
+        )),
+        brand: shoe_payload.brand.as_deref().and_then(normalize_brand),
+        shoe_size: parse_shoe_size(&shoe_payload.size),
+        size: shoe_payload.size,
+        shoe_url: shoe_payload.shoe_url,
+        image_hash: shoe_payload.image_hash,
+        price: shoe_payload.price,
+        sale_price: None,
+        quantity: shoe_payload.quantity,
+        low_stock_threshold: None,
+        like: 0,
+        created_at: Timestamp::now(),
+        updated_at: None,
+        deleted_at: None,
+        restock_date: None,
+        sold: None,
+        condition: Some(shoe_payload.condition.unwrap_or_default()),
+        published: Some(published),
+        published_at: published.then(time),
+    };
+
+    do_insert(&shoe);
+    _set_images(shoe.id, shoe_payload.images.unwrap_or_default());
+    _audit("add_shoe", Some(shoe.id));
+    Ok(shoe)
+}
+
+// get all the available shoes in the store
+#[ic_cdk::query]
+fn get_shoes() -> Vec<Shoe> {
+    let mut shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow_mut();
+        storage
+            .iter()
+            .filter(|(_, item)| _is_listed(item))
+            .map(|(_, item)| item.clone())
+            .collect()
+    });
+    _sort_shoes(&mut shoes, get_default_sort(), false);
+    shoes
+}
+
+// get all the shoes in the store, marking the ones listed by verified sellers
+#[ic_cdk::query]
+fn get_shoe_listings() -> Vec<ShoeListing> {
+    get_shoes().into_iter().map(_to_listing).collect()
+}
+
+// list (id, last change) pairs for the active shoes so an indexer knows what to crawl
+#[ic_cdk::query]
+fn sitemap_entries() -> Vec<(u64, u64)> {
+    get_shoes()
+        .into_iter()
+        .map(|shoe| {
+            (
+                shoe.id,
+                shoe.updated_at.unwrap_or(shoe.created_at).as_nanos(),
+            )
+        })
+        .collect()
+}
+
+// get the store's shoes as summaries along with their average rating and review count
+#[ic_cdk::query]
+fn get_shoes_with_ratings() -> Vec<ShoeWithRating> {
+    get_shoes()
+        .into_iter()
+        .map(|shoe| {
+            let reviews = _shoe_reviews(shoe.id);
+            let review_count = reviews.len() as u64;
+            let average_rating = if review_count == 0 {
+                0.0
+            } else {
+                reviews
+                    .iter()
+                    .map(|review| review.rating as f64)
+                    .sum::<f64>()
+                    / review_count as f64
+            };
+            ShoeWithRating {
+                shoe: _to_summary(&shoe),
+                average_rating,
+                review_count,
+            }
+        })
+        .collect()
+}
+
+// function to retrieve details of a specific Shoe by the shoe id
+#[ic_cdk::query]
+fn get_shoe_by_id(id: u64) -> Result<Shoe, Error> {
+    match _get_shoe(&id) {
+        Some(shoe) => Ok(shoe),
+        None => Err(Error::NotFound {
+            msg: format!("a shoe with id={} not found", id),
+        }),
+    }
+}
+
+// function converting a timestamp such as a shoe's `created_at` to milliseconds since the epoch
+#[ic_cdk::query]
+fn timestamp_to_millis(timestamp: Timestamp) -> u64 {
+    timestamp.as_millis()
+}
+
+// function converting a timestamp such as a shoe's `created_at` to seconds since the epoch
+#[ic_cdk::query]
+fn timestamp_to_secs(timestamp: Timestamp) -> u64 {
+    timestamp.as_secs()
+}
+
+// function to retrieve a shoe with the ids of its neighbours in ascending id order, skipping
+// deleted and unpublished shoes
+#[ic_cdk::query]
+fn get_shoe_with_neighbors(id: u64) -> Result<ShoeNav, Error> {
+    let current = get_shoe_by_id(id)?;
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        let prev = storage
+            .range(..id)
+            .filter(|(_, item)| _is_listed(item))
+            .last()
+            .map(|(key, _)| key);
+        let next = storage
+            .range((Bound::Excluded(id), Bound::Unbounded))
+            .find(|(_, item)| _is_listed(item))
+            .map(|(key, _)| key);
+        Ok(ShoeNav {
+            prev,
+            current,
+            next,
+        })
+    })
+}
+
+// function to retrieve details of a specific Shoe by the shoe id, or None when it doesn't exist
+#[ic_cdk::query]
+fn get_shoe_by_id_opt(id: u64) -> Option<Shoe> {
+    _get_shoe(&id)
+}
+
+// function for clients polling a product page: the shoe if it changed after `since`, or None when it didn't
+#[ic_cdk::query]
+fn shoe_changed_since(id: u64, since: u64) -> Result<Option<Shoe>, Error> {
+    let shoe = get_shoe_by_id(id)?;
+    if shoe.updated_at.unwrap_or(shoe.created_at).as_nanos() > since {
+        Ok(Some(shoe))
+    } else {
+        Ok(None)
+    }
+}
+
+// function to resolve cached ids to shoes in the same order, with None for ids that are
+// missing or soft-deleted
+#[ic_cdk::query]
+fn resolve_shoe_ids(ids: Vec<u64>) -> Vec<Option<Shoe>> {
+    ids.iter()
+        .map(|id| _get_shoe(id).filter(_is_listed))
+        .collect()
+}
+
+// function to retrieve a specific Shoe serialized as JSON, e.g. for off-chain indexers
+#[ic_cdk::query]
+fn get_shoe_json(id: u64) -> Result<String, Error> {
+    match _get_shoe(&id) {
+        Some(shoe) => Ok(serde_json::to_string(&shoe).expect("cannot serialize shoe")),
+        None => Err(Error::NotFound {
+            msg: format!("a shoe with id={} not found", id),
+        }),
+    }
+}
+
+// function to retrieve a specific Shoe by its SKU
+#[ic_cdk::query]
+fn get_shoe_by_sku(sku: String) -> Result<Shoe, Error> {
+    let sku = normalize_sku(&sku);
+    SKU_INDEX
+        .with(|service| service.borrow().get(&StorableString(sku.clone())))
+        .and_then(|id| _get_shoe(&id))
+        .ok_or(Error::NotFound {
+            msg: format!("a shoe with SKU {} not found", sku),
+        })
+}
+
+// function to retrieve a single field of a specific Shoe as text. unset optional fields are empty
+#[ic_cdk::query]
+fn get_shoe_field(id: u64, field: ShoeField) -> Result<String, Error> {
+    let shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("a shoe with id={} not found", id),
+    })?;
+    let value = match field {
+        ShoeField::Owner => shoe.owner,
+        ShoeField::Name => shoe.name,
+        ShoeField::Sku => shoe.sku.unwrap_or_default(),
+        ShoeField::Category => _category_of(&shoe),
+        ShoeField::Size => shoe.size,
+        ShoeField::ShoeUrl => shoe.shoe_url,
+        ShoeField::Price => shoe.price.to_string(),
+        ShoeField::SalePrice => shoe
+            .sale_price
+            .map(|price| price.to_string())
+            .unwrap_or_default(),
+        ShoeField::Quantity => shoe.quantity.to_string(),
+        ShoeField::Like => shoe.like.to_string(),
+        ShoeField::CreatedAt => shoe.created_at.as_nanos().to_string(),
+        ShoeField::UpdatedAt => shoe
+            .updated_at
+            .map(|time| time.as_nanos().to_string())
+            .unwrap_or_default(),
+    };
+    Ok(value)
+}
+
+// function to retrieve the owner of a specific Shoe without fetching the whole shoe
+#[ic_cdk::query]
+fn get_shoe_owner(id: u64) -> Result<Principal, Error> {
+    match _get_shoe(&id) {
+        Some(shoe) => Principal::from_text(&shoe.owner).map_err(|_| Error::NotFound {
+            msg: format!("owner of the shoe with id={} not found", id),
+        }),
+        None => Err(Error::NotFound {
+            msg: format!("a shoe with id={} not found", id),
+        }),
+    }
+}
+
+// Get Total number of shoes available in the store
+#[ic_cdk::query]
+fn total_number_of_shoes() -> i16 {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow_mut();
+        storage.iter().map(|(_, item)| item.quantity).sum()
+    })
+}
+
+// Get the number of listings in the store in constant time
+#[ic_cdk::query]
+fn listing_count() -> u64 {
+    SHOE_STORAGE.with(|service| service.borrow().len())
+}
+
+// Function that modify the details of a shoe
+#[ic_cdk::update]
+fn update_shoe(id: u64, payload: ShoePayload) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    // A missing shoe is reported as NotFound before ownership is checked
+    let mut shoe = _get_active_owned_shoe(id)?;
+    let payload = negotiate_schema(payload)?;
+    validate_min_price(payload.price)?;
+    validate_max_quantity(payload.quantity)?;
+    if let Some(images) = &payload.images {
+        validate_images(images)?;
+    }
+    if let Some(sku) = &payload.sku {
+        validate_sku(sku, Some(id))?;
+    }
+    if let Some(category) = &payload.category {
+        validate_category(category)?;
+    }
+    if let Some(brand) = &payload.brand {
+        validate_brand(brand)?;
+    }
+    shoe.name = payload.name;
+    if let Some(brand) = payload.brand {
+        shoe.brand = normalize_brand(&brand);
+    }
+    if let Some(sku) = payload.sku {
+        shoe.sku = Some(normalize_sku(&sku));
+    }
+    if let Some(category) = payload.category {
+        shoe.category = Some(normalize_category(&category));
+    }
+    shoe.shoe_size = parse_shoe_size(&payload.size);
+    shoe.size = payload.size;
+    shoe.price = payload.price;
+    shoe.shoe_url = payload.shoe_url;
+    if let Some(images) = payload.images {
+        _set_images(id, images);
+    }
+    shoe.image_hash = payload.image_hash.or(shoe.image_hash);
+    shoe.quantity = payload.quantity;
+    shoe.condition = payload.condition.or(shoe.condition);
+    let now = Timestamp::now();
+    if let Some(published) = payload.published {
+        _mark_published(&mut shoe, published, now.as_nanos());
+    }
+    shoe.updated_at = Some(now);
+    do_insert(&shoe);
+    _audit("update_shoe", Some(id));
+    Ok(shoe)
+}
+
+// Function that lists one of the caller's shoes again in another size, copying its name, url,
+// price, category and brand. the new listing gets no SKU, as SKUs are unique per listing
+#[ic_cdk::update]
+fn clone_shoe_with_size(id: u64, size: String, quantity: i16) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let source = _get_active_owned_shoe(id)?;
+    add_shoe(_size_variant_payload(source, size, quantity), None)
+}
+
+// Function that modifies only the provided details of a shoe
+#[ic_cdk::update]
+fn patch_shoe(id: u64, update: ShoeUpdate) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_active_owned_shoe(id)?;
+    _apply_update(&mut shoe, update)?;
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    _audit("patch_shoe", Some(id));
+    Ok(shoe)
+}
+
+// Function that sets a shoe's quantity to `new` only if it's still `expected`, so external
+// inventory syncs don't overwrite a change they haven't seen
+#[ic_cdk::update]
+fn cas_quantity(id: u64, expected: i16, new: i16) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    _cas_quantity_as(id, expected, new, caller(), time())
+}
+
+// Function that sets the quantity at or below which a shoe shows up in the owner's low-stock alerts
+#[ic_cdk::update]
+fn set_low_stock_threshold(id: u64, threshold: i16) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_active_owned_shoe(id)?;
+    if threshold < 0 {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid low-stock threshold {}", threshold),
+        });
+    }
+    shoe.low_stock_threshold = Some(threshold);
+    do_insert(&shoe);
+    Ok(shoe)
+}
+
+// Function that puts a shoe on sale at `sale_price`, or takes it off sale when `None`
+#[ic_cdk::update]
+fn set_sale_price(id: u64, sale_price: Option<i16>) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_active_owned_shoe(id)?;
+    if let Some(sale_price) = sale_price {
+        _validate_sale_price(&shoe, sale_price)?;
+    }
+    shoe.sale_price = sale_price;
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    Ok(shoe)
+}
+
+// Function that advertises when an out-of-stock shoe is back, or clears the date when `None`
+#[ic_cdk::update]
+fn set_restock_date(id: u64, date: Option<u64>) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_active_owned_shoe(id)?;
+    if date.is_some_and(|date| date <= time()) {
+        return Err(Error::ValidationError {
+            msg: "A restock date must be in the future".to_string(),
+        });
+    }
+    shoe.restock_date = date;
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    Ok(shoe)
+}
+
+// Get the listed shoes with a restock date in the future, soonest first
+#[ic_cdk::query]
+fn get_upcoming_restocks() -> Vec<Shoe> {
+    _upcoming_restocks(time())
+}
+
+// Get the image urls of a shoe, in display order
+#[ic_cdk::query]
+fn get_shoe_images(id: u64) -> Result<Vec<String>, Error> {
+    get_shoe_by_id(id)?;
+    Ok(_get_images(id))
+}
+
+// Get the tags of a shoe
+#[ic_cdk::query]
+fn get_shoe_tags(id: u64) -> Result<Vec<String>, Error> {
+    get_shoe_by_id(id)?;
+    Ok(_get_tags(id))
+}
+
+// Function that replaces the tags of a shoe, returning them as stored
+#[ic_cdk::update]
+fn set_shoe_tags(id: u64, tags: Vec<String>) -> Result<Vec<String>, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_active_owned_shoe(id)?;
+    let tags = validate_tags(&tags)?;
+    _set_tags(id, tags.clone());
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    Ok(tags)
+}
+
+// Function that appends an image to a shoe, returning its images
+#[ic_cdk::update]
+fn add_shoe_image(id: u64, url: String) -> Result<Vec<String>, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_active_owned_shoe(id)?;
+    let mut images = _get_images(id);
+    images.push(url);
+    validate_images(&images)?;
+    _set_images(id, images.clone());
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    Ok(images)
+}
+
+// Function that removes the image at `index` from a shoe, returning its remaining images
+#[ic_cdk::update]
+fn remove_shoe_image(id: u64, index: u64) -> Result<Vec<String>, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_active_owned_shoe(id)?;
+    let mut images = _get_images(id);
+    if index as usize >= images.len() {
+        return Err(Error::NotFound {
+            msg: format!("the shoe with id={} has no image at index {}", id, index),
+        });
+    }
+    images.remove(index as usize);
+    _set_images(id, images.clone());
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    Ok(images)
+}
+
+// Function that overrides the seller's return policy for one shoe, or clears the override when `None`.
+// returns the return policy that now applies to the shoe
+#[ic_cdk::update]
+fn set_shoe_return_policy(id: u64, return_policy: Option<String>) -> Result<String, Error> {
+    _ensure_store_open()?;
+    _get_active_owned_shoe(id)?;
+    _set_return_policy(id, return_policy)?;
+    get_shoe_return_policy(id)
+}
+
+// Get the return policy that applies to a shoe: its own override, else its seller's policy
+#[ic_cdk::query]
+fn get_shoe_return_policy(id: u64) -> Result<String, Error> {
+    let shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("a shoe with id={} not found", id),
+    })?;
+    let own_policy = SHOE_RETURN_POLICIES.with(|service| service.borrow().get(&id));
+    Ok(own_policy.map(|policy| policy.0).unwrap_or_else(|| {
+        Principal::from_text(&shoe.owner)
+            .ok()
+            .and_then(|owner| get_seller_profile(owner).ok())
+            .map(|profile| profile.return_policy)
+            .unwrap_or_default()
+    }))
+}
+
+// Function that restocks several shoes at once, returning a result per (id, amount) pair
+#[ic_cdk::update]
+fn restock_bulk(updates: Vec<(u64, i16)>) -> Vec<Result<Shoe, Error>> {
+    updates
+        .into_iter()
+        .map(|(id, amount)| _restock_shoe(id, amount))
+        .collect()
+}
+
+// Function that moves every shoe owned by the caller to `new_owner`, returning how many moved.
+// traps when asked to transfer to the anonymous principal
+#[ic_cdk::update]
+fn transfer_all_my_shoes(new_owner: Principal) -> u64 {
+    _trap_if_store_closed();
+    if new_owner == Principal::anonymous() {
+        ic_cdk::trap("Cannot transfer shoes to the anonymous principal");
+    }
+    let moved = _transfer_shoes(&caller().to_string(), new_owner, time());
+    for id in &moved {
+        _audit("transfer_shoe", Some(*id));
+    }
+    moved.len() as u64
+}
+
+// Function that moves the caller's shoes in category `from` to category `to`, returning how many moved.
+// soft-deleted shoes are left alone, and a `to` category that isn't allowed traps
+#[ic_cdk::update]
+fn recategorize_my_shoes(from: String, to: String) -> u64 {
+    _trap_if_store_closed();
+    match _recategorize(&caller().to_string(), &from, &to, time()) {
+        Ok(moved) => moved,
+        Err(Error::ValidationError { msg }) => ic_cdk::trap(&msg),
+        Err(_) => ic_cdk::trap("Cannot recategorize the shoes"),
+    }
+}
+
+// Search Shoe Items by Name
+#[ic_cdk::query]
+fn search_by_name(name: String) -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow_mut();
+        storage
+            .iter()
+            .filter(|(_, item)| _is_listed(item) && item.name == name)
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Search Shoe Items whose name has a word starting with each word of `query`, ignoring case.
+// candidates come from the name index, so only the matching words are visited
+#[ic_cdk::query]
+fn search_by_name_contains(query: String) -> Vec<Shoe> {
+    let tokens = _name_tokens(&query);
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    let mut candidates: Option<Vec<u64>> = None;
+    for token in &tokens {
+        let Some(ids) = _name_index_prefix(token) else {
+            // a full bucket no longer lists every shoe, so fall back to a scan
+            return _scan_by_name_tokens(&tokens);
+        };
+        candidates = Some(match candidates {
+            None => ids,
+            Some(previous) => previous.into_iter().filter(|id| ids.contains(id)).collect(),
+        });
+    }
+    candidates
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|id| _get_shoe(&id))
+        .filter(_is_listed)
+        .collect()
+}
+
+// Search Shoe Items by size, ignoring case and whitespace ("US 10" matches "us10").
+// candidates come from the size index instead of a scan of the store
+#[ic_cdk::query]
+fn search_by_size(size: String) -> Vec<Shoe> {
+    let key = _size_key(&size);
+    if key.is_empty() {
+        return Vec::new();
+    }
+    let bucket = _size_index_key(&size)
+        .map(StorableString)
+        .filter(|key| !SIZE_INDEX_OVERFLOW.with(|service| service.borrow().contains_key(key)))
+        .map(|key| SIZE_INDEX.with(|service| service.borrow().get(&key).unwrap_or_default()));
+    match bucket {
+        Some(ids) => ids
+            .0
+            .into_iter()
+            .filter_map(|id| _get_shoe(&id))
+            .filter(_is_listed)
+            .collect(),
+        // an overflowed size or a size too long for the index, so fall back to a scan
+        None => SHOE_STORAGE.with(|service| {
+            let storage = service.borrow();
+            storage
+                .iter()
+                .filter(|(_, item)| _is_listed(item) && _size_key(&item.size) == key)
+                .map(|(_, item)| item)
+                .collect()
+        }),
+    }
+}
+
+// Search the listed shoes for the words of `query` in their name, category and tags, ignoring
+// case. a word matches a word of the shoe starting with it, and the shoes matching the most
+// words come first. `limit` is capped at the max page size
+#[ic_cdk::query]
+fn full_text_search(query: String, limit: u64) -> Vec<Shoe> {
+    let tokens = _name_tokens(&query);
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+    let mut ranked: Vec<(usize, Shoe)> = get_shoes()
+        .into_iter()
+        .filter_map(|shoe| {
+            let mut words = _name_tokens(&shoe.name);
+            words.extend(_name_tokens(&_category_of(&shoe)));
+            for tag in _get_tags(shoe.id) {
+                words.extend(_name_tokens(&tag));
+            }
+            let matches = tokens
+                .iter()
+                .filter(|token| words.iter().any(|word| word.starts_with(token.as_str())))
+                .count();
+            (matches > 0).then_some((matches, shoe))
+        })
+        .collect();
+    ranked.sort_by_key(|(matches, _)| std::cmp::Reverse(*matches));
+    ranked
+        .into_iter()
+        .take(limit.min(get_max_page_size()) as usize)
+        .map(|(_, shoe)| shoe)
+        .collect()
+}
+
+// Get the size grid of a product: every listed shoe with the same name, ignoring case and
+// extra whitespace, ordered like size_counts
+#[ic_cdk::query]
+fn size_matrix(name: String) -> Vec<SizeAvailability> {
+    let normalize_name = |name: &str| {
+        name.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    let name = normalize_name(&name);
+    let mut variants: Vec<SizeAvailability> = search_by_name_contains(name.clone())
+        .into_iter()
+        .filter(|shoe| normalize_name(&shoe.name) == name)
+        .map(|shoe| SizeAvailability {
+            size: normalize_size(&shoe.size),
+            shoe_id: shoe.id,
+            quantity: shoe.quantity,
+            price: shoe.price,
+        })
+        .collect();
+    variants.sort_by(|a, b| _compare_sizes(&a.size, &b.size));
+    variants
+}
+
+// Convert a shoe size between sizing systems using the standard size chart. sizes between
+// chart rows are interpolated and the result is rounded to the nearest half size
+#[ic_cdk::query]
+fn convert_size(value: f32, from: SizeSystem, to: SizeSystem) -> f32 {
+    _convert_size(value, from, to)
+}
+
+// Search Shoe Items by a size in a given sizing system
+#[ic_cdk::query]
+fn search_by_size_system(value: f32, system: SizeSystem) -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                _is_listed(item)
+                    && item.shoe_size.is_some_and(|size| {
+                        size.system == system && (size.value - value).abs() < f32::EPSILON
+                    })
+            })
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Check whether a hash matches the image hash the seller set for a shoe.
+// shoes without an image hash never match
+#[ic_cdk::query]
+fn verify_image_hash(id: u64, hash: String) -> Result<bool, Error> {
+    match _get_shoe(&id) {
+        Some(shoe) => Ok(shoe
+            .image_hash
+            .is_some_and(|image_hash| image_hash.trim().eq_ignore_ascii_case(hash.trim()))),
+        None => Err(Error::NotFound {
+            msg: format!("a shoe with id={} not found", id),
+        }),
+    }
+}
+
+// Get the shoes whose image url is missing, to help clean up the catalog
+#[ic_cdk::query]
+fn get_shoes_missing_image() -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.shoe_url.trim().is_empty())
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Get each normalized size with the number of in-stock listings in it, for search facets.
+// sizes are ordered by sizing system and then numerically, followed by non-numeric sizes
+#[ic_cdk::query]
+fn size_counts() -> Vec<(String, u64)> {
+    let mut counts: std::collections::BTreeMap<String, u64> = std::collections::BTreeMap::new();
+    for shoe in get_shoes() {
+        let size = normalize_size(&shoe.size);
+        if shoe.quantity > 0 && !size.is_empty() {
+            *counts.entry(size).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+    counts.sort_by(|(a, _), (b, _)| _compare_sizes(a, b));
+    counts
+}
+
+// Get the listed shoes in a given condition
+#[ic_cdk::query]
+fn get_shoes_by_condition(condition: ShoeCondition) -> Vec<Shoe> {
+    get_shoes()
+        .into_iter()
+        .filter(|shoe| shoe.condition.unwrap_or_default() == condition)
+        .collect()
+}
+
+// Get the listed shoes of a brand, ignoring case. candidates come from the brand index
+#[ic_cdk::query]
+fn get_shoes_by_brand(brand: String) -> Vec<Shoe> {
+    let Some(brand) = normalize_brand(&brand) else {
+        return Vec::new();
+    };
+    let key = StorableString(brand.clone());
+    if !BRAND_INDEX_OVERFLOW.with(|service| service.borrow().contains_key(&key)) {
+        let bucket = BRAND_INDEX.with(|service| service.borrow().get(&key).unwrap_or_default());
+        return bucket
+            .0
+            .into_iter()
+            .filter_map(|id| _get_shoe(&id))
+            .filter(_is_listed)
+            .collect();
+    }
+    // an overflowed brand no longer lists every shoe, so fall back to a scan
+    get_shoes()
+        .into_iter()
+        .filter(|shoe| shoe.brand.as_ref() == Some(&brand))
+        .collect()
+}
+
+// Get the brands of the listed shoes, in alphabetical order
+#[ic_cdk::query]
+fn distinct_brands() -> Vec<String> {
+    BRAND_INDEX.with(|service| {
+        let index = service.borrow();
+        index
+            .iter()
+            .filter(|(_, ids)| {
+                ids.0
+                    .iter()
+                    .any(|id| _get_shoe(id).is_some_and(|shoe| _is_listed(&shoe)))
+            })
+            .map(|(brand, _)| brand.0)
+            .collect()
+    })
+}
+
+// Get the shoes whose size is blank, a common import bug
+#[ic_cdk::query]
+fn get_shoes_missing_size() -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.size.trim().is_empty())
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Get the shoes with a zero or negative price, to clean up legacy listings
+#[ic_cdk::query]
+fn get_invalid_price_shoes() -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.price <= 0)
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Get the shoes owned by any of the given principals. only the first
+// MAX_OWNERS_PER_QUERY owners are considered
+#[ic_cdk::query]
+fn get_shoes_by_owners(owners: Vec<Principal>) -> Vec<Shoe> {
+    let owners: Vec<String> = owners
+        .iter()
+        .take(MAX_OWNERS_PER_QUERY)
+        .map(|owner| owner.to_string())
+        .collect();
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| _is_listed(item) && owners.contains(&item.owner))
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Get the caller's shoes whose quantity is at or below their low-stock threshold
+#[ic_cdk::query]
+fn get_low_stock_alerts() -> Vec<Shoe> {
+    _low_stock_alerts(&caller().to_string())
+}
+
+// Get up to `limit` shoes on sale, biggest discount `(price - sale_price) / price` first.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_best_deals(limit: u64) -> Vec<Shoe> {
+    let mut deals: Vec<(f64, Shoe)> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| _is_listed(item))
+            .filter_map(|(_, item)| Some((_discount(&item)?, item)))
+            .collect()
+    });
+    deals.sort_by(|a, b| b.0.total_cmp(&a.0));
+    deals
+        .into_iter()
+        .take(limit.min(get_max_page_size()) as usize)
+        .map(|(_, item)| item)
+        .collect()
+}
+
+// Get the listed shoes on sale along with their discount as a whole percentage
+#[ic_cdk::query]
+fn get_shoes_on_sale_with_percent() -> Vec<(Shoe, u8)> {
+    get_shoes()
+        .into_iter()
+        .filter_map(|shoe| {
+            let percent = _discount_percent(&shoe)?;
+            Some((shoe, percent))
+        })
+        .collect()
+}
+
+// Get the `limit` listed shoes with the most units sold, best sellers first.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_best_sellers(limit: u64) -> Vec<Shoe> {
+    let mut shoes: Vec<Shoe> = get_shoes()
+        .into_iter()
+        .filter(|shoe| shoe.sold.unwrap_or(0) > 0)
+        .collect();
+    shoes.sort_by_key(|shoe| std::cmp::Reverse(shoe.sold.unwrap_or(0)));
+    shoes.truncate(limit.min(get_max_page_size()) as usize);
+    shoes
+}
+
+// Get up to `limit` in-stock shoes priced within `tolerance` of `price`, closest price first.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn shoes_near_price(price: i16, tolerance: i16, limit: u64) -> Vec<Shoe> {
+    let distance = |shoe: &Shoe| (shoe.price as i32 - price as i32).abs();
+    let mut shoes: Vec<Shoe> = get_shoes()
+        .into_iter()
+        .filter(|shoe| shoe.quantity > 0 && distance(shoe) <= tolerance as i32)
+        .collect();
+    shoes.sort_by_key(distance);
+    shoes.truncate(limit.min(get_max_page_size()) as usize);
+    shoes
+}
+
+// Get the shoes that were never updated since being created more than `older_than_secs` seconds ago
+#[ic_cdk::query]
+fn get_stale_shoes(older_than_secs: u64) -> Vec<Shoe> {
+    _stale_shoes(older_than_secs, time())
+}
+
+// Admin query for the shoes in a given state. admin-only, as it includes soft-deleted shoes
+#[ic_cdk::query]
+fn get_shoes_by_status(status: ShoeStatus) -> Vec<Shoe> {
+    if !_is_admin() {
+        ic_cdk::trap("Only an admin can view shoes by status");
+    }
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| _shoe_status(item) == status)
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Get a summary of the caller's listings for the seller dashboard
+#[ic_cdk::query]
+fn my_dashboard() -> SellerDashboard {
+    _dashboard(&caller().to_string())
+}
+
+// Get the caller's `limit` most recently created shoes, newest first.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_my_newest_shoes(limit: u64) -> Vec<Shoe> {
+    _newest_shoes_of(&caller().to_string(), limit)
+}
+
+// Get all of the caller's shoes sorted by `sort`
+#[ic_cdk::query]
+fn get_my_shoes_sorted(sort: SortBy, descending: bool) -> Vec<Shoe> {
+    _sorted_shoes_of(&caller().to_string(), sort, descending)
+}
+
+// Get the listed shoes that went live between `start` and `end` (inclusive), oldest first
+#[ic_cdk::query]
+fn get_shoes_published_between(start: u64, end: u64) -> Vec<Shoe> {
+    let mut shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .map(|(_, item)| item)
+            .filter(|item| {
+                _is_listed(item) && item.published_at.is_some_and(|at| at >= start && at <= end)
+            })
+            .collect()
+    });
+    shoes.sort_by_key(|shoe| shoe.published_at);
+    shoes
+}
+
+// Get all of the caller's shoes, including unpublished drafts
+#[ic_cdk::query]
+fn get_my_shoes() -> Vec<Shoe> {
+    _shoes_of(&caller().to_string())
+}
+
+// Get the caller's shoes in a category, ignoring case
+#[ic_cdk::query]
+fn get_my_shoes_in_category(category: String) -> Vec<Shoe> {
+    _shoes_in_category_of(&caller().to_string(), &category)
+}
+
+// Get the caller's shoes that aren't deleted, priced at `min_price` or more with at most
+// `max_likes` likes, e.g. to find listings worth discounting or removing
+#[ic_cdk::query]
+fn get_my_underperformers(min_price: i16, max_likes: u32) -> Vec<Shoe> {
+    _underperformers_of(&caller().to_string(), min_price, max_likes)
+}
+
+// Get the caller's shoes with the value of their stock (`price * quantity`), sorted by that value
+#[ic_cdk::query]
+fn get_my_shoes_by_value(descending: bool) -> Vec<(Shoe, u64)> {
+    _shoes_by_value_of(&caller().to_string(), descending)
+}
+
+// Get the store's stock figures as of now, for off-chain accounting to compare across periods
+#[ic_cdk::query]
+fn inventory_snapshot() -> InventorySnapshot {
+    _inventory_snapshot(time())
+}
+
+// Get up to `limit` other in-stock shoes related to the given shoe: shoes in the same
+// category come first, and within each group the closest prices come first.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_related_shoes(id: u64, limit: u64) -> Result<Vec<Shoe>, Error> {
+    let shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("a shoe with id={} not found", id),
+    })?;
+    let mut related: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.id != shoe.id && item.quantity > 0 && _is_listed(item))
+            .map(|(_, item)| item)
+            .collect()
+    });
+    related.sort_by_key(|item| {
+        (
+            _category_of(item) != _category_of(&shoe),
+            (item.price as i32 - shoe.price as i32).abs(),
+        )
+    });
+    related.truncate(limit.min(get_max_page_size()) as usize);
+    Ok(related)
+}
+
+// Filter, sort and paginate the shoes in the store in one call.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn search_and_sort(
+    filter: ShoeFilter,
+    sort: SortBy,
+    descending: bool,
+    offset: u64,
+    limit: u64,
+) -> PagedShoes {
+    let name = filter.name.map(|name| name.trim().to_lowercase());
+    let category = filter
+        .category
+        .map(|category| normalize_category(&category));
+    let mut shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                _is_listed(item)
+                    && name
+                        .as_ref()
+                        .is_none_or(|name| item.name.to_lowercase().contains(name))
+                    && category
+                        .as_ref()
+                        .is_none_or(|category| &_category_of(item) == category)
+                    && filter
+                        .min_price
+                        .is_none_or(|min_price| item.price >= min_price)
+                    && filter
+                        .max_price
+                        .is_none_or(|max_price| item.price <= max_price)
+                    && filter
+                        .in_stock
+                        .is_none_or(|in_stock| (item.quantity > 0) == in_stock)
+            })
+            .map(|(_, item)| item)
+            .collect()
+    });
+    _sort_shoes(&mut shoes, sort, descending);
+    let limit = limit.min(get_max_page_size());
+    PagedShoes {
+        total: shoes.len() as u64,
+        shoes: shoes
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect(),
+        offset,
+        limit,
+    }
+}
+
+// Search Shoe Items whose size, converted to `system`, falls within [min, max]
+#[ic_cdk::query]
+fn search_by_size_range(min: f32, max: f32, system: SizeSystem) -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                _is_listed(item)
+                    && item.shoe_size.is_some_and(|size| {
+                        let value = _convert_size(size.value, size.system, system);
+                        value >= min && value <= max
+                    })
+            })
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Get the shoes created within [start, end]; an inverted range returns no shoes
+#[ic_cdk::query]
+fn get_shoes_created_between(start: u64, end: u64) -> Vec<Shoe> {
+    if start > end {
+        return Vec::new();
+    }
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                _is_listed(item)
+                    && item.created_at.as_nanos() >= start
+                    && item.created_at.as_nanos() <= end
+            })
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// Get the top `limit` trending shoes, ranked by `like^like_weight / (age_in_seconds + 1)^recency_weight`
+// where the age is the time elapsed since `created_at`, so recent likes weigh more than old ones.
+// the ranking is read from the cache kept by the heartbeat when it covers `limit`.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_trending_shoes(limit: u64) -> Vec<Shoe> {
+    let limit = limit.min(get_max_page_size());
+    let cached_ids = TRENDING_CACHE.with(|cache| {
+        cache
+            .borrow()
+            .as_ref()
+            .filter(|_| limit <= TRENDING_CACHE_SIZE)
+            .map(|cache| cache.ids.clone())
+    });
+    match cached_ids {
+        Some(ids) => ids
+            .iter()
+            .filter_map(_get_shoe)
+            .filter(_is_listed)
+            .take(limit as usize)
+            .collect(),
+        None => _compute_trending(limit, time()),
+    }
+}
+
+// Get the weights of the trending score
+#[ic_cdk::query]
+fn get_trending_weights() -> TrendingWeights {
+    TRENDING_WEIGHTS.with(|weights| *weights.borrow().get())
+}
+
+// Admin function that sets the weights of the trending score. the ranking is recomputed on the next read
+#[ic_cdk::update]
+fn set_trending_weights(weights: TrendingWeights) -> Result<TrendingWeights, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the trending weights".to_string(),
+            caller: caller(),
+        });
+    }
+    let is_valid = |weight: f64| weight.is_finite() && weight >= 0.0;
+    if !is_valid(weights.like_weight) || !is_valid(weights.recency_weight) {
+        return Err(Error::ValidationError {
+            msg: "The trending weights must be finite and not negative".to_string(),
+        });
+    }
+    TRENDING_WEIGHTS
+        .with(|cell| cell.borrow_mut().set(weights))
+        .expect("cannot set the trending weights");
+    TRENDING_CACHE.with(|cache| *cache.borrow_mut() = None);
+    Ok(weights)
+}
+
+// recompute the trending cache once every TRENDING_REFRESH_INTERVAL
+#[ic_cdk::heartbeat]
+fn heartbeat() {
+    let now = time();
+    let is_stale = TRENDING_CACHE.with(|cache| {
+        cache
+            .borrow()
+            .as_ref()
+            .is_none_or(|cache| now.saturating_sub(cache.computed_at) >= TRENDING_REFRESH_INTERVAL)
+    });
+    if is_stale {
+        _refresh_trending_cache(now);
+    }
+}
+
+// Get a page of the principals that liked a shoe, in the order they liked it.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_likers(id: u64, offset: u64, limit: u64) -> Result<Vec<Principal>, Error> {
+    if _get_shoe(&id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a shoe with id={} not found", id),
+        });
+    }
+    let mut likes = _shoe_likes(id);
+    likes.sort_by_key(|(_, liked_at)| *liked_at);
+    Ok(likes
+        .into_iter()
+        .map(|(liker, _)| liker)
+        .skip(offset as usize)
+        .take(limit.min(get_max_page_size()) as usize)
+        .collect())
+}
+
+// Get how many likes were given between `start` (inclusive) and `end` (exclusive), in nanoseconds.
+// likes given before likes were timestamped count as given when their shoe was created
+#[ic_cdk::query]
+fn likes_in_range(start: u64, end: u64) -> u64 {
+    SHOE_LIKES.with(|service| {
+        service
+            .borrow()
+            .iter()
+            .filter(|(_, liked_at)| (start..end).contains(liked_at))
+            .count() as u64
+    })
+}
+
+// Function that likes a shoe by its id
+#[ic_cdk::update]
+fn like_shoe(id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let shoe = _like_shoe_as(id, caller(), time())?;
+    _audit("like_shoe", Some(id));
+    Ok(shoe)
+}
+
+// Function that likes several shoes at once, returning a result per id
+#[ic_cdk::update]
+fn like_shoes_bulk(ids: Vec<u64>) -> Vec<Result<Shoe, Error>> {
+    ids.into_iter().map(like_shoe).collect()
+}
+
+// Function that removes the caller's like from a shoe by its id
+#[ic_cdk::update]
+fn unlike_shoe(id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let shoe = _unlike_shoe_as(id, caller())?;
+    _audit("unlike_shoe", Some(id));
+    Ok(shoe)
+}
+
+// Get the `limit` principals that have given the most likes, most likes first.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_top_likers(limit: u64) -> Vec<(Principal, u64)> {
+    let mut likers: Vec<(Principal, u64)> = LIKES_GIVEN.with(|service| {
+        let likes_given = service.borrow();
+        likes_given
+            .iter()
+            .map(|(liker, count)| (liker.0, count))
+            .collect()
+    });
+    likers.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    likers.truncate(limit.min(get_max_page_size()) as usize);
+    likers
+}
+
+// Get how many listed shoes each seller has, most listings first
+#[ic_cdk::query]
+fn seller_listing_counts() -> Vec<(Principal, u64)> {
+    let mut counts: std::collections::BTreeMap<Principal, u64> = std::collections::BTreeMap::new();
+    for shoe in get_shoes() {
+        if let Ok(owner) = Principal::from_text(&shoe.owner) {
+            *counts.entry(owner).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(Principal, u64)> = counts.into_iter().collect();
+    counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    counts
+}
+
+// Get the maximum number of likes a single shoe can receive
+#[ic_cdk::query]
+fn get_like_limit() -> u64 {
+    LIKE_LIMIT.with(|limit| *limit.borrow().get())
+}
+
+// Get the seconds a principal has to wait between two likes. 0 means no cooldown
+#[ic_cdk::query]
+fn get_like_cooldown() -> u64 {
+    LIKE_COOLDOWN.with(|cooldown| *cooldown.borrow().get())
+}
+
+// Admin function that sets the seconds a principal has to wait between two likes
+#[ic_cdk::update]
+fn set_like_cooldown(cooldown_secs: u64) -> Result<u64, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the like cooldown".to_string(),
+            caller: caller(),
+        });
+    }
+    if cooldown_secs.checked_mul(1_000_000_000).is_none() {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid like cooldown {}", cooldown_secs),
+        });
+    }
+    LIKE_COOLDOWN
+        .with(|cell| cell.borrow_mut().set(cooldown_secs))
+        .expect("cannot set the like cooldown");
+    Ok(cooldown_secs)
+}
+
+// Admin function that sets the maximum number of likes a single shoe can receive
+#[ic_cdk::update]
+fn set_like_limit(limit: u64) -> Result<u64, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the like limit".to_string(),
+            caller: caller(),
+        });
+    }
+    LIKE_LIMIT
+        .with(|cell| cell.borrow_mut().set(limit))
+        .expect("cannot set the like limit");
+    Ok(limit)
+}
+
+// Get the minimum price a shoe can be listed at
+#[ic_cdk::query]
+fn get_min_price() -> i16 {
+    MIN_PRICE.with(|min_price| *min_price.borrow().get() as i16)
+}
+
+// Admin function that sets the minimum price a shoe can be listed at
+#[ic_cdk::update]
+fn set_min_price(min_price: i16) -> Result<i16, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the minimum price".to_string(),
+            caller: caller(),
+        });
+    }
+    if min_price < 0 {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid minimum price {}", min_price),
+        });
+    }
+    MIN_PRICE
+        .with(|cell| cell.borrow_mut().set(min_price as u64))
+        .expect("cannot set the minimum price");
+    Ok(min_price)
+}
+
+// Function that buys `amount` units of a shoe and records the purchase. units the caller has
+// reserved count towards it, and its reservations of the shoe are consumed
+#[ic_cdk::update]
+fn purchase_shoe(id: u64, amount: i16) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let shoe = _purchase_shoe_as(id, amount, caller(), time())?;
+    _audit("purchase_shoe", Some(id));
+    Ok(shoe)
+}
+
+// Get the purchases made by the caller
+#[ic_cdk::query]
+fn get_my_purchases() -> Vec<Purchase> {
+    _purchases_of(caller())
+}
+
+// Function that adds a shoe to the caller's wishlist, returning the wishlist's length.
+// a wishlist holds at most MAX_WISHLIST_LEN shoes
+#[ic_cdk::update]
+fn add_to_wishlist(id: u64) -> Result<u64, Error> {
+    _ensure_store_open()?;
+    _add_to_wishlist_as(id, caller())
+}
+
+// Function that removes a shoe from the caller's wishlist, returning the wishlist's length
+#[ic_cdk::update]
+fn remove_from_wishlist(id: u64) -> u64 {
+    _trap_if_store_closed();
+    let owner = caller();
+    let mut wishlist = _wishlist_of(owner);
+    wishlist.retain(|wished| *wished != id);
+    _save_wishlist(owner, &wishlist);
+    wishlist.len() as u64
+}
+
+// Function that empties the caller's wishlist, returning how many shoes were removed
+#[ic_cdk::update]
+fn clear_my_wishlist() -> u64 {
+    _trap_if_store_closed();
+    let cleared = _wishlist_of(caller()).len() as u64;
+    WISHLISTS.with(|service| service.borrow_mut().remove(&StorablePrincipal(caller())));
+    cleared
+}
+
+// Get the shoes on the caller's wishlist that are still listed
+#[ic_cdk::query]
+fn get_my_wishlist() -> Vec<Shoe> {
+    _wishlist_of(caller())
+        .into_iter()
+        .filter_map(|id| _get_shoe(&id))
+        .filter(_is_listed)
+        .collect()
+}
+
+// Get the distinct principals that liked or purchased any of the caller's shoes
+#[ic_cdk::query]
+fn interactors_for_my_shoes() -> Vec<Principal> {
+    _interactors_of(&caller().to_string())
+}
+
+// Get the share of the principals that liked or purchased a shoe who liked it, from 0 to 1
+#[ic_cdk::query]
+fn like_rate(id: u64) -> Result<f64, Error> {
+    if _get_shoe(&id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a shoe with id={} not found", id),
+        });
+    }
+    let likers: std::collections::BTreeSet<Principal> = _shoe_likes(id)
+        .into_iter()
+        .map(|(liker, _)| liker)
+        .collect();
+    let mut interactors = likers.clone();
+    PURCHASE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        interactors.extend(
+            storage
+                .iter()
+                .filter(|(_, purchase)| purchase.shoe_id == id)
+                .map(|(_, purchase)| purchase.buyer),
+        );
+    });
+    if interactors.is_empty() {
+        return Ok(0.0);
+    }
+    Ok(likers.len() as f64 / interactors.len() as f64)
+}
+
+// Get the maximum quantity a single listing can hold
+#[ic_cdk::query]
+fn get_max_quantity() -> i16 {
+    MAX_QUANTITY.with(|max_quantity| *max_quantity.borrow().get() as i16)
+}
+
+// Admin function that sets the maximum quantity a single listing can hold
+#[ic_cdk::update]
+fn set_max_quantity(max_quantity: i16) -> Result<i16, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the maximum quantity".to_string(),
+            caller: caller(),
+        });
+    }
+    if max_quantity <= 0 {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid maximum quantity {}", max_quantity),
+        });
+    }
+    MAX_QUANTITY
+        .with(|cell| cell.borrow_mut().set(max_quantity as u64))
+        .expect("cannot set the maximum quantity");
+    Ok(max_quantity)
+}
+
+// Admin function that rewrites every stored size in its canonical form, returning how many shoes changed
+#[ic_cdk::update]
+fn normalize_all_sizes() -> Result<u64, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can normalize the sizes".to_string(),
+            caller: caller(),
+        });
+    }
+    let shoes: Vec<Shoe> =
+        SHOE_STORAGE.with(|service| service.borrow().iter().map(|(_, item)| item).collect());
+    let mut changed = 0;
+    for mut shoe in shoes {
+        let size = normalize_size(&shoe.size);
+        if size != shoe.size {
+            shoe.shoe_size = parse_shoe_size(&size);
+            shoe.size = size;
+            shoe.updated_at = Some(Timestamp::now());
+            do_insert(&shoe);
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+// Admin function that moves the shoe id counter to just past the highest stored id, returning
+// the id the next shoe will get. the counter never moves backwards, so ids of hard-deleted shoes
+// that purchases, wishlists or the audit log may still point at are never handed out again
+#[ic_cdk::update]
+fn reset_id_counter() -> Result<u64, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can reset the id counter".to_string(),
+            caller: caller(),
+        });
+    }
+    let next_id = _next_free_id();
+    ID_COUNTER
+        .with(|counter| counter.borrow_mut().set(next_id))
+        .expect("cannot reset id counter");
+    Ok(next_id)
+}
+
+// Get the balance the caller has accrued from sales
+#[ic_cdk::query]
+fn get_my_balance() -> u64 {
+    _balance_of(caller())
+}
+
+// Admin query for the balances every seller has accrued from sales
+#[ic_cdk::query]
+fn get_all_balances() -> Vec<(Principal, u64)> {
+    if !_is_admin() {
+        ic_cdk::trap("Only an admin can view all balances");
+    }
+    LEDGER.with(|service| {
+        let ledger = service.borrow();
+        ledger
+            .iter()
+            .map(|(seller, balance)| (seller.0, balance))
+            .collect()
+    })
+}
+
+// Update function to delete a specific shoe by its id
+#[ic_cdk::update]
+fn delete_shoe(id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    // A missing shoe is reported as NotFound before ownership is checked
+    _get_owned_shoe(id)?;
+    // Attempt to remove the shoe from storage based on its unique identifier
+    match _remove_shoe(id) {
+        Some(shoe) => {
+            _audit("delete_shoe", Some(id));
+            Ok(shoe)
+        }
+        None => Err(Error::NotFound {
+            msg: format!("couldn't delete a shoe with id={}. shoe not found.", id),
+        }),
+    }
+}
+
+// Function that merges a duplicate listing into another one of the caller's listings: the
+// quantities, sales and likes are combined onto `keep_id`, which keeps its own details, and
+// `remove_id` is deleted
+#[ic_cdk::update]
+fn merge_shoes(keep_id: u64, remove_id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let shoe = _merge_shoes_as(keep_id, remove_id, caller(), time())?;
+    _audit("merge_shoes", Some(keep_id));
+    Ok(shoe)
+}
+
+// Function that makes a staged shoe visible in the public listings
+#[ic_cdk::update]
+fn publish_shoe(id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    _set_published_as(id, true, caller(), time())
+}
+
+// Function that takes a shoe out of the public listings as a draft
+#[ic_cdk::update]
+fn unpublish_shoe(id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    _set_published_as(id, false, caller(), time())
+}
+
+// Function that hides a shoe from the public listings while keeping it restorable
+#[ic_cdk::update]
+fn soft_delete_shoe(id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_owned_shoe(id)?;
+    if shoe.deleted_at.is_none() {
+        shoe.deleted_at = Some(time());
+        do_insert(&shoe);
+        _audit("soft_delete_shoe", Some(id));
+    }
+    Ok(shoe)
+}
+
+// Function that restores a soft-deleted shoe to the public listings
+#[ic_cdk::update]
+fn restore_shoe(id: u64) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _get_owned_shoe(id)?;
+    if shoe.deleted_at.is_some() {
+        shoe.deleted_at = None;
+        shoe.updated_at = Some(Timestamp::now());
+        do_insert(&shoe);
+        _audit("restore_shoe", Some(id));
+    }
+    Ok(shoe)
+}
+
+// Get the recorded changes of a shoe, oldest first
+#[ic_cdk::query]
+fn get_shoe_history(id: u64) -> Vec<HistoryEntry> {
+    HISTORY_STORAGE.with(|service| service.borrow().get(&id).unwrap_or_default().0)
+}
+
+// Get the price of a shoe over time as (timestamp, price) points for a chart, oldest first: the
+// earliest price known from the history at `created_at`, each recorded price change, and the
+// current price now
+#[ic_cdk::query]
+fn price_history(id: u64) -> Result<Vec<(u64, i16)>, Error> {
+    _price_history(id, time())
+}
+
+// Check whether a principal is a verified seller
+#[ic_cdk::query]
+fn is_verified_seller(seller: Principal) -> bool {
+    VERIFIED_SELLERS.with(|service| service.borrow().contains_key(&StorablePrincipal(seller)))
+}
+
+// Get the categories shoes can be listed in. an empty list allows every category
+#[ic_cdk::query]
+fn get_allowed_categories() -> Vec<String> {
+    ALLOWED_CATEGORIES.with(|service| {
+        let allowlist = service.borrow();
+        allowlist.iter().map(|(category, _)| category.0).collect()
+    })
+}
+
+// Admin function that adds a category to the allowlist, returning the allowlist
+#[ic_cdk::update]
+fn add_allowed_category(category: String) -> Result<Vec<String>, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can manage the allowed categories".to_string(),
+            caller: caller(),
+        });
+    }
+    let category = normalize_category(&category);
+    if category.is_empty() || category.len() > StorableString::MAX_SIZE as usize {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A category must be between 1 and {} bytes long",
+                StorableString::MAX_SIZE
+            ),
+        });
+    }
+    ALLOWED_CATEGORIES.with(|service| service.borrow_mut().insert(StorableString(category), ()));
+    Ok(get_allowed_categories())
+}
+
+// Admin function that removes a category from the allowlist, returning the allowlist
+#[ic_cdk::update]
+fn remove_allowed_category(category: String) -> Result<Vec<String>, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can manage the allowed categories".to_string(),
+            caller: caller(),
+        });
+    }
+    let category = normalize_category(&category);
+    ALLOWED_CATEGORIES.with(|service| service.borrow_mut().remove(&StorableString(category)));
+    Ok(get_allowed_categories())
+}
+
+// Admin function that marks a principal as a verified seller
+#[ic_cdk::update]
+fn verify_seller(seller: Principal) -> Result<(), Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can verify sellers".to_string(),
+            caller: caller(),
+        });
+    }
+    VERIFIED_SELLERS.with(|service| service.borrow_mut().insert(StorablePrincipal(seller), ()));
+    Ok(())
+}
+
+// Admin function that removes a principal from the verified sellers
+#[ic_cdk::update]
+fn unverify_seller(seller: Principal) -> Result<(), Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can unverify sellers".to_string(),
+            caller: caller(),
+        });
+    }
+    VERIFIED_SELLERS.with(|service| service.borrow_mut().remove(&StorablePrincipal(seller)));
+    Ok(())
+}
+
+// Function that reports a shoe for admin review, returning its report count.
+// each principal can report a shoe once
+#[ic_cdk::update]
+fn report_shoe(id: u64) -> Result<u64, Error> {
+    _ensure_store_open()?;
+    let reports = _report_shoe_as(id, caller(), time())?;
+    _audit("report_shoe", Some(id));
+    Ok(reports)
+}
+
+// Function that rates a shoe from MIN_RATING to MAX_RATING. reviewing a shoe again replaces the earlier review
+#[ic_cdk::update]
+fn review_shoe(shoe_id: u64, rating: u8, text: String) -> Result<Review, Error> {
+    _ensure_store_open()?;
+    _review_shoe_as(shoe_id, rating, text, caller(), time())
+}
+
+// Get the reviews of a shoe
+#[ic_cdk::query]
+fn get_reviews(shoe_id: u64) -> Vec<Review> {
+    _shoe_reviews(shoe_id)
+}
+
+// Admin query for the shoes without an owner, left behind by legacy data
+#[ic_cdk::query]
+fn get_orphan_shoes() -> Vec<Shoe> {
+    if !_is_admin() {
+        ic_cdk::trap("Only an admin can view orphan shoes");
+    }
+    _orphan_shoes()
+}
+
+// Admin function that gives a shoe without an owner to `owner`
+#[ic_cdk::update]
+fn assign_orphan_owner(id: u64, owner: Principal) -> Result<Shoe, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can assign orphan shoes".to_string(),
+            caller: caller(),
+        });
+    }
+    _assign_orphan(id, owner, time())
+}
+
+// Admin query for a page of the actions `actor` took, oldest first, for investigating abuse.
+// `limit` is capped at the max page size
+#[ic_cdk::query]
+fn get_actions_by(actor: Principal, offset: u64, limit: u64) -> Vec<AuditEntry> {
+    if !_is_admin() {
+        ic_cdk::trap("Only an admin can view the audit log");
+    }
+    _actions_by(actor, offset, limit)
+}
+
+// Admin query for the shoes with at least `min_reports` reports and their report count, most reported first
+#[ic_cdk::query]
+fn get_flagged_shoes(min_reports: u64) -> Vec<(Shoe, u64)> {
+    if !_is_admin() {
+        ic_cdk::trap("Only an admin can view flagged shoes");
+    }
+    _flagged_shoes(min_reports)
+}
+
+// Function that adds a comment to a shoe
+#[ic_cdk::update]
+fn add_comment(shoe_id: u64, text: String) -> Result<Comment, Error> {
+    _ensure_store_open()?;
+    _add_comment_as(shoe_id, text, caller(), time())
+}
+
+// Function that deletes a comment. only its author or an admin can delete it
+#[ic_cdk::update]
+fn delete_comment(comment_id: u64) -> Result<Comment, Error> {
+    _ensure_store_open()?;
+    _delete_comment_as(comment_id, caller(), _is_admin())
+}
+
+// Get the comments on a shoe, oldest first
+#[ic_cdk::query]
+fn get_comments(shoe_id: u64) -> Vec<Comment> {
+    COMMENT_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, comment)| comment.shoe_id == shoe_id)
+            .map(|(_, comment)| comment)
+            .collect()
+    })
+}
+
+// Function that sets the caller's seller profile
+#[ic_cdk::update]
+fn set_seller_profile(profile: SellerProfile) -> Result<SellerProfile, Error> {
+    _ensure_store_open()?;
+    if profile.display_name.len() > MAX_DISPLAY_NAME_LEN {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A display name can be at most {} bytes long",
+                MAX_DISPLAY_NAME_LEN
+            ),
+        });
+    }
+    validate_return_policy(&profile.return_policy)?;
+    SELLER_PROFILES.with(|service| {
+        service
+            .borrow_mut()
+            .insert(StorablePrincipal(caller()), profile.clone())
+    });
+    Ok(profile)
+}
+
+// Get the profile of a seller
+#[ic_cdk::query]
+fn get_seller_profile(seller: Principal) -> Result<SellerProfile, Error> {
+    SELLER_PROFILES
+        .with(|service| service.borrow().get(&StorablePrincipal(seller)))
+        .ok_or(Error::NotFound {
+            msg: format!("a seller profile for {} not found", seller),
+        })
+}
+
+// Get the name to show for a shoe's seller: their profile's display name, else their principal
+#[ic_cdk::query]
+fn get_shoe_seller_name(id: u64) -> Result<String, Error> {
+    let shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("a shoe with id={} not found", id),
+    })?;
+    let display_name = Principal::from_text(&shoe.owner)
+        .ok()
+        .and_then(|seller| get_seller_profile(seller).ok())
+        .map(|profile| profile.display_name)
+        .filter(|display_name| !display_name.trim().is_empty());
+    Ok(display_name.unwrap_or(shoe.owner))
+}
+
+// Get the shoes whose price dropped after `since`, with the old and new price of their latest drop
+#[ic_cdk::query]
+fn get_recent_price_drops(since: u64) -> Vec<(Shoe, i16, i16)> {
+    HISTORY_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter_map(|(id, history)| {
+                let (old_price, new_price) = history
+                    .0
+                    .iter()
+                    .rev()
+                    .filter(|entry| entry.field == "price" && entry.timestamp > since)
+                    .filter_map(|entry| {
+                        Some((entry.old_value.parse().ok()?, entry.new_value.parse().ok()?))
+                    })
+                    .find(|(old_price, new_price): &(i16, i16)| new_price < old_price)?;
+                let shoe = _get_shoe(&id).filter(_is_listed)?;
+                Some((shoe, old_price, new_price))
+            })
+            .collect()
+    })
+}
+
+// Admin function that adds a shoe to, or removes it from, the featured shoes
+#[ic_cdk::update]
+fn set_featured(id: u64, featured: bool) -> Result<u64, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can feature shoes".to_string(),
+            caller: caller(),
+        });
+    }
+    _set_featured(id, featured)
+}
+
+// Admin function that features or unfeatures several shoes at once, returning a result per id
+#[ic_cdk::update]
+fn set_featured_bulk(ids: Vec<u64>, featured: bool) -> Vec<Result<u64, Error>> {
+    if !_is_admin() {
+        return ids
+            .iter()
+            .map(|_| {
+                Err(Error::NotAuthorized {
+                    msg: "Only an admin can feature shoes".to_string(),
+                    caller: caller(),
+                })
+            })
+            .collect();
+    }
+    _set_featured_bulk(ids, featured)
+}
+
+// Get the featured shoes, in ascending id order
+#[ic_cdk::query]
+fn get_featured_shoes() -> Vec<Shoe> {
+    FEATURED_SHOES.with(|service| {
+        let featured = service.borrow();
+        featured
+            .iter()
+            .filter_map(|(id, _)| _get_shoe(&id))
+            .filter(_is_listed)
+            .collect()
+    })
+}
+
+// Get one listed featured shoe picked pseudo-randomly from the current time, e.g. for a hero banner.
+// None when no listed shoe is featured
+#[ic_cdk::query]
+fn random_featured_shoe() -> Option<Shoe> {
+    _pick_featured(time())
+}
+
+// Get the order get_shoes lists the shoes in
+#[ic_cdk::query]
+fn get_default_sort() -> SortBy {
+    DEFAULT_SORT.with(|sort| *sort.borrow().get())
+}
+
+// Admin function that sets the order get_shoes lists the shoes in
+#[ic_cdk::update]
+fn set_default_sort(sort: SortBy) -> Result<SortBy, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the default sort".to_string(),
+            caller: caller(),
+        });
+    }
+    DEFAULT_SORT
+        .with(|cell| cell.borrow_mut().set(sort))
+        .expect("cannot set the default sort");
+    Ok(sort)
+}
+
+// Get the most items a paginated query returns at once
+#[ic_cdk::query]
+fn get_max_page_size() -> u64 {
+    MAX_PAGE_SIZE.with(|size| *size.borrow().get())
+}
+
+// Admin function that sets the most items a paginated query returns at once
+#[ic_cdk::update]
+fn set_max_page_size(max_page_size: u64) -> Result<u64, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the max page size".to_string(),
+            caller: caller(),
+        });
+    }
+    if max_page_size == 0 {
+        return Err(Error::ValidationError {
+            msg: "The max page size must be at least 1".to_string(),
+        });
+    }
+    MAX_PAGE_SIZE
+        .with(|cell| cell.borrow_mut().set(max_page_size))
+        .expect("cannot set the max page size");
+    Ok(max_page_size)
+}
+
+// Get the rotation interval of the featured shoes in seconds
+#[ic_cdk::query]
+fn get_featured_rotation_interval() -> u64 {
+    FEATURED_ROTATION_INTERVAL.with(|interval| *interval.borrow().get())
+}
+
+// Admin function that sets the rotation interval of the featured shoes in seconds
+#[ic_cdk::update]
+fn set_featured_rotation_interval(interval_secs: u64) -> Result<u64, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the featured rotation interval".to_string(),
+            caller: caller(),
+        });
+    }
+    if interval_secs == 0 {
+        return Err(Error::ValidationError {
+            msg: "The featured rotation interval must be at least one second".to_string(),
+        });
+    }
+    FEATURED_ROTATION_INTERVAL
+        .with(|cell| cell.borrow_mut().set(interval_secs))
+        .expect("cannot set the featured rotation interval");
+    Ok(interval_secs)
+}
+
+// Get `count` featured shoes that rotate every rotation interval. the pick is deterministic:
+// the featured shoes are taken in ascending id order, starting at the index of the current
+// interval since the epoch (wrapping around the pool), so every call within one interval
+// returns the same shoes. `count` is capped at the max page size
+#[ic_cdk::query]
+fn get_rotating_featured(count: u64) -> Vec<Shoe> {
+    _rotating_window(
+        get_featured_shoes(),
+        count.min(get_max_page_size()),
+        time(),
+        get_featured_rotation_interval(),
+    )
+}
+
+// Function that holds `amount` units of a shoe for the caller for RESERVATION_TTL.
+// the units are taken out of stock until the reservation is released or expires
+#[ic_cdk::update]
+fn reserve_shoe(id: u64, amount: i16) -> Result<Reservation, Error> {
+    _ensure_store_open()?;
+    _reserve_shoe_as(id, amount, caller(), time())
+}
+
+// Function that releases one of the caller's reservations, returning its units to stock
+#[ic_cdk::update]
+fn release_reservation(reservation_id: u64) -> Result<Reservation, Error> {
+    _ensure_store_open()?;
+    _release_reservation_as(reservation_id, caller())
+}
+
+// Get the caller's reservations that haven't been released yet
+#[ic_cdk::query]
+fn get_my_reservations() -> Vec<Reservation> {
+    _reservations_of(caller())
+}
+
+#[ic_cdk::init]
+fn init() {
+    _start_reservation_cleanup();
+}
+
+// timers don't survive an upgrade, so the cleanup timer is started again. likes still stored
+// on the shoes are moved out, shoes without a condition are marked New and the indexes are
+// refreshed, as shoes stored before an index was added aren't in it
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    _migrate_legacy_likes();
+    _default_conditions();
+    _reindex_shoes();
+    _start_reservation_cleanup();
+}
+
+// Get the rules checked before a shoe is added or liked
+#[ic_cdk::query]
+fn get_policy() -> Policy {
+    POLICY.with(|policy| policy.borrow().get().clone())
+}
+
+// Admin function that sets the rules checked before a shoe is added or liked
+#[ic_cdk::update]
+fn set_policy(policy: Policy) -> Result<Policy, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the policy".to_string(),
+            caller: caller(),
+        });
+    }
+    if policy.max_actions_per_minute == Some(0) {
+        return Err(Error::ValidationError {
+            msg: "The rate limit must allow at least one action per minute".to_string(),
+        });
+    }
+    POLICY
+        .with(|cell| cell.borrow_mut().set(policy.clone()))
+        .expect("cannot set the policy");
+    Ok(policy)
+}
+
+// Admin function that adds a principal to the blocklist, or removes it when `blocked` is false
+#[ic_cdk::update]
+fn set_blocked(principal: Principal, blocked: bool) -> Result<(), Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can manage the blocklist".to_string(),
+            caller: caller(),
+        });
+    }
+    BLOCKLIST.with(|service| {
+        let mut blocklist = service.borrow_mut();
+        if blocked {
+            blocklist.insert(StorablePrincipal(principal), ());
+        } else {
+            blocklist.remove(&StorablePrincipal(principal));
+        }
+    });
+    Ok(())
+}
+
+// Admin query for the principals on the blocklist
+#[ic_cdk::query]
+fn get_blocklist() -> Vec<Principal> {
+    if !_is_admin() {
+        ic_cdk::trap("Only an admin can view the blocklist");
+    }
+    BLOCKLIST.with(|service| {
+        let blocklist = service.borrow();
+        blocklist.iter().map(|(principal, _)| principal.0).collect()
+    })
+}
+
+// Check whether the store accepts changes. queries keep working while it's closed
+#[ic_cdk::query]
+fn is_store_open() -> bool {
+    STORE_OPEN.with(|open| *open.borrow().get() != 0)
+}
+
+// Admin function that opens or closes the store, e.g. during maintenance. admin endpoints
+// keep working while it's closed
+#[ic_cdk::update]
+fn set_store_open(open: bool) -> Result<bool, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can open or close the store".to_string(),
+            caller: caller(),
+        });
+    }
+    STORE_OPEN
+        .with(|cell| cell.borrow_mut().set(open as u64))
+        .expect("cannot set the store open flag");
+    Ok(open)
+}
+
+// Get the principal of the caller; anonymous callers get the anonymous principal
+#[ic_cdk::query]
+fn whoami() -> Principal {
+    caller()
+}
+
+// Check whether the caller is an admin of the store
+#[ic_cdk::query]
+fn am_i_admin() -> bool {
+    _is_admin()
+}
+
+// Get the currency the store settles prices in
+#[ic_cdk::query]
+fn get_store_currency() -> String {
+    STORE_CURRENCY.with(|currency| currency.borrow().get().clone())
+}
+
+// Admin function that sets the currency the store settles prices in
+#[ic_cdk::update]
+fn set_store_currency(currency: String) -> Result<String, Error> {
+    if !_is_admin() {
+        return Err(Error::NotAuthorized {
+            msg: "Only an admin can set the store currency".to_string(),
+            caller: caller(),
+        });
+    }
+    let currency = currency.trim().to_uppercase();
+    if currency.is_empty() || currency.len() > MAX_CURRENCY_LEN {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid currency code '{}'", currency),
+        });
+    }
+    STORE_CURRENCY
+        .with(|cell| cell.borrow_mut().set(currency.clone()))
+        .expect("cannot set the store currency");
+    Ok(currency)
+}
+
+// Convert a price into another currency given a rate in basis points (10_000 = 1.0),
+// rounding half up to the nearest whole unit
+#[ic_cdk::query]
+fn convert_price(price: u64, rate_basis_points: u64) -> u64 {
+    let converted = (price as u128 * rate_basis_points as u128 + 5_000) / 10_000;
+    u64::try_from(converted).unwrap_or(u64::MAX)
+}
+
+#[derive(candid::CandidType, Deserialize, Serialize)]
+#[allow(clippy::enum_variant_names)]
+enum Error {
+    NotFound { msg: String },
+    NotAuthorized { msg: String, caller: Principal },
+    AlreadyLiked { msg: String },
+    LikeLimitReached { msg: String },
+    NotLiked { msg: String },
+    InsufficientStock { msg: String },
+    AlreadyReported { msg: String },
+    ShoeDeleted { msg: String, id: u64 },
+    DuplicateSku { msg: String },
+    ValidationError { msg: String },
+    WishlistFull { msg: String },
+    QuantityMismatch { msg: String, current: i16 },
+    RateLimited { msg: String },
+    StoreClosed { msg: String },
+    UnsupportedSchema { msg: String, version: u16 },
+}
+
+// helper method to perform insert. changes to an existing shoe are recorded in its history
+fn do_insert(shoe: &Shoe) {
+    let previous = SHOE_STORAGE.with(|service| service.borrow_mut().insert(shoe.id, shoe.clone()));
+    _update_indexes(previous.as_ref(), Some(shoe));
+    if let Some(previous) = previous {
+        _record_history(&previous, shoe);
+    }
+}
+
+// helper method to save a stock change from a purchase. these aren't recorded in the shoe's
+// history, so they don't push the seller's own edits, e.g. price changes, out of it
+fn _insert_stock_change(shoe: &Shoe) {
+    let previous = SHOE_STORAGE.with(|service| service.borrow_mut().insert(shoe.id, shoe.clone()));
+    _update_indexes(previous.as_ref(), Some(shoe));
+}
+
+// helper method to move a shoe's entries in the secondary indexes from its previous version
+// to its new one. `None` stands for a shoe that didn't exist before or was removed
+fn _update_indexes(previous: Option<&Shoe>, current: Option<&Shoe>) {
+    let id = previous.or(current).map(|shoe| shoe.id).unwrap_or_default();
+    _move_in_index(
+        &NAME_INDEX,
+        &NAME_INDEX_OVERFLOW,
+        id,
+        previous
+            .map(|shoe| _name_tokens(&shoe.name))
+            .unwrap_or_default(),
+        current
+            .map(|shoe| _name_tokens(&shoe.name))
+            .unwrap_or_default(),
+    );
+    _move_in_index(
+        &BRAND_INDEX,
+        &BRAND_INDEX_OVERFLOW,
+        id,
+        previous
+            .and_then(|shoe| shoe.brand.clone())
+            .into_iter()
+            .collect(),
+        current
+            .and_then(|shoe| shoe.brand.clone())
+            .into_iter()
+            .collect(),
+    );
+    _move_in_index(
+        &SIZE_INDEX,
+        &SIZE_INDEX_OVERFLOW,
+        id,
+        previous
+            .and_then(|shoe| _size_index_key(&shoe.size))
+            .into_iter()
+            .collect(),
+        current
+            .and_then(|shoe| _size_index_key(&shoe.size))
+            .into_iter()
+            .collect(),
+    );
+    let previous_sku = previous
+        .and_then(|shoe| shoe.sku.as_deref())
+        .unwrap_or_default();
+    let current_sku = current
+        .and_then(|shoe| shoe.sku.as_deref())
+        .unwrap_or_default();
+    if previous_sku != current_sku {
+        SKU_INDEX.with(|service| {
+            let mut index = service.borrow_mut();
+            if !previous_sku.is_empty() {
+                index.remove(&StorableString(previous_sku.to_string()));
+            }
+            if let Some(shoe) = current.filter(|_| !current_sku.is_empty()) {
+                index.insert(StorableString(current_sku.to_string()), shoe.id);
+            }
+        });
+    }
+}
+
+// helper method to file a shoe id under `current_keys` instead of `previous_keys` in a secondary index.
+// a key whose bucket is full is marked as overflowed, as its bucket no longer lists every shoe
+fn _move_in_index(
+    index: &'static std::thread::LocalKey<RefCell<IdIndex>>,
+    overflowed: &'static std::thread::LocalKey<RefCell<OverflowKeys>>,
+    id: u64,
+    previous_keys: Vec<String>,
+    current_keys: Vec<String>,
+) {
+    index.with(|service| {
+        let mut index = service.borrow_mut();
+        for key in previous_keys
+            .iter()
+            .filter(|key| !current_keys.contains(key))
+        {
+            let key = StorableString(key.clone());
+            let mut ids = index.get(&key).unwrap_or_default();
+            ids.0.retain(|indexed| *indexed != id);
+            if ids.0.is_empty() {
+                index.remove(&key);
+            } else {
+                index.insert(key, ids);
+            }
+        }
+        for key in current_keys {
+            let key = StorableString(key);
+            let mut ids = index.get(&key).unwrap_or_default();
+            if ids.0.contains(&id) {
+                continue;
+            }
+            if ids.0.len() < MAX_IDS_PER_INDEX_KEY {
+                ids.0.push(id);
+                index.insert(key, ids);
+            } else {
+                overflowed.with(|service| service.borrow_mut().insert(key, ()));
+            }
+        }
+    });
+}
+
+// helper method to empty a secondary index and its overflow marks. used in _reindex_shoes
+fn _clear_index(
+    index: &'static std::thread::LocalKey<RefCell<IdIndex>>,
+    overflowed: &'static std::thread::LocalKey<RefCell<OverflowKeys>>,
+) {
+    index.with(|service| {
+        let keys: Vec<StorableString> = service.borrow().iter().map(|(key, _)| key).collect();
+        for key in keys {
+            service.borrow_mut().remove(&key);
+        }
+    });
+    overflowed.with(|service| {
+        let keys: Vec<StorableString> = service.borrow().iter().map(|(key, _)| key).collect();
+        for key in keys {
+            service.borrow_mut().remove(&key);
+        }
+    });
+}
+
+// helper method to split a name into lowercase words, each cut to fit an index key. used in the name index
+fn _name_tokens(name: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    for word in name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let mut token = String::new();
+        for c in word.to_lowercase().chars() {
+            if token.len() + c.len_utf8() > StorableString::MAX_SIZE as usize {
+                break;
+            }
+            token.push(c);
+        }
+        if !tokens.contains(&token) {
+            tokens.push(token);
+        }
+    }
+    tokens
+}
+
+// helper method to normalize a size for comparison by dropping whitespace and case
+fn _size_key(size: &str) -> String {
+    size.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+// helper method to get the size index key of a size. blank sizes and sizes too long
+// for an index key aren't indexed
+fn _size_index_key(size: &str) -> Option<String> {
+    let key = _size_key(size);
+    (!key.is_empty() && key.len() <= StorableString::MAX_SIZE as usize).then_some(key)
+}
+
+// helper method to order normalized sizes by sizing system and then numerically, followed by
+// non-numeric sizes. used in size_counts/size_matrix
+fn _compare_sizes(a: &str, b: &str) -> std::cmp::Ordering {
+    match (_numeric_size(a), _numeric_size(b)) {
+        (Some((a_label, a_value)), Some((b_label, b_value))) => a_label
+            .cmp(b_label)
+            .then(a_value.total_cmp(&b_value))
+            .then(a.cmp(b)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+// helper method to split a normalized size such as "us 10.5" or "10" into its system label
+// and number. used in _compare_sizes
+fn _numeric_size(size: &str) -> Option<(&str, f32)> {
+    let (label, value) = size.rsplit_once(' ').unwrap_or(("", size));
+    value.parse::<f32>().ok().map(|value| (label, value))
+}
+
+// helper method to collect the ids filed under every name token starting with `prefix`.
+// returns None when one of those tokens overflowed and its bucket may be missing shoes
+fn _name_index_prefix(prefix: &str) -> Option<Vec<u64>> {
+    let overflowed = NAME_INDEX_OVERFLOW.with(|service| {
+        service
+            .borrow()
+            .range(StorableString(prefix.to_string())..)
+            .take_while(|(token, _)| token.0.starts_with(prefix))
+            .next()
+            .is_some()
+    });
+    if overflowed {
+        return None;
+    }
+    NAME_INDEX.with(|service| {
+        let index = service.borrow();
+        let mut ids: Vec<u64> = Vec::new();
+        for (_, bucket) in index
+            .range(StorableString(prefix.to_string())..)
+            .take_while(|(token, _)| token.0.starts_with(prefix))
+        {
+            for id in bucket.0 {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        Some(ids)
+    })
+}
+
+// helper method to match the listed shoes against name tokens without the index. used in search_by_name_contains
+fn _scan_by_name_tokens(tokens: &[String]) -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                let name_tokens = _name_tokens(&item.name);
+                _is_listed(item)
+                    && tokens
+                        .iter()
+                        .all(|token| name_tokens.iter().any(|name| name.starts_with(token)))
+            })
+            .map(|(_, item)| item)
+            .collect()
+    })
+}
+
+// helper method to mark the shoes stored before conditions existed as New. used in post_upgrade
+fn _default_conditions() {
+    let shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.condition.is_none())
+            .map(|(_, item)| item)
+            .collect()
+    });
+    for mut shoe in shoes {
+        shoe.condition = Some(ShoeCondition::New);
+        SHOE_STORAGE.with(|service| service.borrow_mut().insert(shoe.id, shoe));
+    }
+}
+
+// helper method to rebuild the secondary indexes from the stored shoes. used in post_upgrade
+// so shoes stored before an index existed are found through it too. rebuilding also clears
+// the overflow marks of keys whose buckets have room again
+fn _reindex_shoes() {
+    _clear_index(&NAME_INDEX, &NAME_INDEX_OVERFLOW);
+    _clear_index(&SIZE_INDEX, &SIZE_INDEX_OVERFLOW);
+    _clear_index(&BRAND_INDEX, &BRAND_INDEX_OVERFLOW);
+    let shoes: Vec<Shoe> =
+        SHOE_STORAGE.with(|service| service.borrow().iter().map(|(_, item)| item).collect());
+    for shoe in &shoes {
+        _update_indexes(None, Some(shoe));
+    }
+}
+
+// helper method to append the tracked fields that changed between two versions of a shoe to its history
+fn _record_history(old: &Shoe, new: &Shoe) {
+    let changes = [
+        ("name", old.name.clone(), new.name.clone()),
+        (
+            "sku",
+            old.sku.clone().unwrap_or_default(),
+            new.sku.clone().unwrap_or_default(),
+        ),
+        ("category", _category_of(old), _category_of(new)),
+        ("size", old.size.clone(), new.size.clone()),
+        ("shoe_url", old.shoe_url.clone(), new.shoe_url.clone()),
+        (
+            "image_hash",
+            old.image_hash.clone().unwrap_or_default(),
+            new.image_hash.clone().unwrap_or_default(),
+        ),
+        ("price", old.price.to_string(), new.price.to_string()),
+        (
+            "quantity",
+            old.quantity.to_string(),
+            new.quantity.to_string(),
+        ),
+    ];
+    if changes
+        .iter()
+        .all(|(_, old_value, new_value)| old_value == new_value)
+    {
+        return;
+    }
+    // every edit of a tracked field stamps updated_at, so the entries share the edit's time
+    let timestamp = new.updated_at.map_or_else(time, Timestamp::as_nanos);
+    let entries: Vec<HistoryEntry> = changes
+        .into_iter()
+        .filter(|(_, old_value, new_value)| old_value != new_value)
+        .map(|(field, old_value, new_value)| HistoryEntry {
+            field: field.to_string(),
+            old_value: old_value.chars().take(MAX_HISTORY_VALUE_LEN).collect(),
+            new_value: new_value.chars().take(MAX_HISTORY_VALUE_LEN).collect(),
+            timestamp,
+        })
+        .collect();
+    HISTORY_STORAGE.with(|service| {
+        let mut storage = service.borrow_mut();
+        let mut history = storage.get(&new.id).unwrap_or_default();
+        history.0.extend(entries);
+        let overflow = history.0.len().saturating_sub(MAX_HISTORY_LEN);
+        history.0.drain(..overflow);
+        storage.insert(new.id, history);
+    });
+}
+
+// a helper method to get a message by id. used in get_message/update_message
+fn _get_shoe(id: &u64) -> Option<Shoe> {
+    SHOE_STORAGE.with(|service| service.borrow().get(id))
+}
+
+// helper method to wrap a shoe with its listing details. used in get_shoe_listings
+fn _to_listing(shoe: Shoe) -> ShoeListing {
+    let verified = Principal::from_text(&shoe.owner).is_ok_and(is_verified_seller);
+    let discount_percent = _discount_percent(&shoe);
+    ShoeListing {
+        shoe,
+        verified,
+        discount_percent,
+    }
+}
+
+// helper method to get the discount of a shoe on sale as a whole percentage. used in _to_listing
+fn _discount_percent(shoe: &Shoe) -> Option<u8> {
+    _discount(shoe).map(|discount| (discount * 100.0).round() as u8)
+}
+
+// helper method to get the discount fraction of a shoe on sale. used in get_best_deals
+fn _discount(shoe: &Shoe) -> Option<f64> {
+    let sale_price = shoe.sale_price?;
+    if shoe.price <= 0 || sale_price >= shoe.price {
+        return None;
+    }
+    Some((shoe.price - sale_price) as f64 / shoe.price as f64)
+}
+
+// helper method to convert a size between sizing systems. used in convert_size
+fn _convert_size(value: f32, from: SizeSystem, to: SizeSystem) -> f32 {
+    if from == to {
+        return value;
+    }
+    let (from, to) = (from as usize, to as usize);
+    // the chart segment containing the value, or the closest end segment when it is outside the chart
+    let segment = SIZE_CHART
+        .windows(2)
+        .position(|rows| value <= rows[1][from])
+        .unwrap_or(SIZE_CHART.len() - 2);
+    let (low, high) = (SIZE_CHART[segment], SIZE_CHART[segment + 1]);
+    let ratio = (value - low[from]) / (high[from] - low[from]);
+    let converted = low[to] + ratio * (high[to] - low[to]);
+    (converted * 2.0).round() / 2.0
+}
+
+// helper method to sort shoes by a key. used in search_and_sort
+fn _sort_shoes(shoes: &mut [Shoe], sort: SortBy, descending: bool) {
+    match sort {
+        SortBy::Price => shoes.sort_by_key(|shoe| shoe.price),
+        SortBy::Likes => shoes.sort_by_key(|shoe| shoe.like),
+        SortBy::CreatedAt => shoes.sort_by_key(|shoe| shoe.created_at),
+        SortBy::Name => shoes.sort_by_key(|shoe| shoe.name.to_lowercase()),
+    }
+    if descending {
+        shoes.reverse();
+    }
+}
+
+// helper method to record a report of a shoe from the given caller at `now`. used in report_shoe
+fn _report_shoe_as(id: u64, caller: Principal, now: u64) -> Result<u64, Error> {
+    let shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("Shoe with ID {} not found. Cannot report.", id),
+    })?;
+    _ensure_listed(&shoe)?;
+    let key = (id, StorablePrincipal(caller));
+    if SHOE_REPORTS.with(|service| service.borrow().contains_key(&key)) {
+        return Err(Error::AlreadyReported {
+            msg: format!(
+                "Shoe with ID {} has already been reported by caller: {}.",
+                id, caller
+            ),
+        });
+    }
+    SHOE_REPORTS.with(|service| service.borrow_mut().insert(key, now));
+    Ok(_report_count(id))
+}
+
+// helper method to list the shoes with at least `min_reports` reports. used in get_flagged_shoes
+fn _flagged_shoes(min_reports: u64) -> Vec<(Shoe, u64)> {
+    let mut flagged: Vec<(Shoe, u64)> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .map(|(id, item)| (item, _report_count(id)))
+            .filter(|(_, count)| *count > 0 && *count >= min_reports)
+            .collect()
+    });
+    flagged.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    flagged
+}
+
+// helper method to list the report keys of a shoe. used in _report_count/_clear_reports
+fn _shoe_report_keys(id: u64) -> Vec<(u64, StorablePrincipal)> {
+    SHOE_REPORTS.with(|service| {
+        service
+            .borrow()
+            .range((id, StorablePrincipal::default())..)
+            .take_while(|((shoe_id, _), _)| *shoe_id == id)
+            .map(|(key, _)| key)
+            .collect()
+    })
+}
+
+// helper method to count the reports of a shoe
+fn _report_count(id: u64) -> u64 {
+    _shoe_report_keys(id).len() as u64
+}
+
+// helper method to remove the reports of a deleted shoe
+fn _clear_reports(id: u64) {
+    for key in _shoe_report_keys(id) {
+        SHOE_REPORTS.with(|service| service.borrow_mut().remove(&key));
+    }
+}
+
+// helper method to rate a shoe as the given reviewer at `now`. used in review_shoe
+fn _review_shoe_as(
+    shoe_id: u64,
+    rating: u8,
+    text: String,
+    caller: Principal,
+    now: u64,
+) -> Result<Review, Error> {
+    let shoe = _get_shoe(&shoe_id).ok_or(Error::NotFound {
+        msg: format!("Shoe with ID {} not found. Cannot review.", shoe_id),
+    })?;
+    _ensure_listed(&shoe)?;
+    if shoe.owner == caller.to_string() {
+        return Err(Error::NotAuthorized {
+            msg: "You can't review your own shoe".to_string(),
+            caller,
+        });
+    }
+    if !(MIN_RATING..=MAX_RATING).contains(&rating) {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "The rating must be between {} and {}",
+                MIN_RATING, MAX_RATING
+            ),
+        });
+    }
+    let text = text.trim().to_string();
+    if text.len() > MAX_COMMENT_LEN {
+        return Err(Error::ValidationError {
+            msg: format!("A review can be at most {} bytes long", MAX_COMMENT_LEN),
+        });
+    }
+    let review = Review {
+        shoe_id,
+        author: caller,
+        rating,
+        text,
+        created_at: now,
+    };
+    REVIEW_STORAGE.with(|service| {
+        service
+            .borrow_mut()
+            .insert((shoe_id, StorablePrincipal(caller)), review.clone())
+    });
+    Ok(review)
+}
+
+// helper method to add a shoe to a principal's wishlist. used in add_to_wishlist
+fn _add_to_wishlist_as(id: u64, owner: Principal) -> Result<u64, Error> {
+    let shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("Shoe with ID {} not found. Cannot add to wishlist.", id),
+    })?;
+    _ensure_listed(&shoe)?;
+    let mut wishlist = _wishlist_of(owner);
+    if !wishlist.contains(&id) {
+        if wishlist.len() >= MAX_WISHLIST_LEN {
+            return Err(Error::WishlistFull {
+                msg: format!("A wishlist can hold at most {} shoes", MAX_WISHLIST_LEN),
+            });
+        }
+        wishlist.push(id);
+    }
+    _save_wishlist(owner, &wishlist);
+    Ok(wishlist.len() as u64)
+}
+
+// helper method to read a principal's wishlist, pruning the ids of shoes that were deleted
+fn _wishlist_of(owner: Principal) -> Vec<u64> {
+    let mut wishlist = WISHLISTS
+        .with(|service| service.borrow().get(&StorablePrincipal(owner)))
+        .unwrap_or_default()
+        .0;
+    wishlist.retain(|id| _get_shoe(id).is_some());
+    wishlist
+}
+
+// helper method to store a principal's wishlist, dropping the entry once it's empty
+fn _save_wishlist(owner: Principal, wishlist: &[u64]) {
+    let key = StorablePrincipal(owner);
+    WISHLISTS.with(|service| {
+        let mut storage = service.borrow_mut();
+        if wishlist.is_empty() {
+            storage.remove(&key);
+        } else {
+            storage.insert(key, IdList(wishlist.to_vec()));
+        }
+    });
+}
+
+// helper method to check a caller against the enabled policy rules. used in add_shoe/_like_shoe_as
+fn _check_policy(action: PolicyAction, caller: Principal, now: u64) -> Result<(), Error> {
+    let policy = get_policy();
+    if policy.no_anonymous && caller == Principal::anonymous() {
+        return Err(Error::NotAuthorized {
+            msg: "Anonymous callers can't add or like shoes".to_string(),
+            caller,
+        });
+    }
+    if policy.blocklist_enabled
+        && BLOCKLIST.with(|service| service.borrow().contains_key(&StorablePrincipal(caller)))
+    {
+        return Err(Error::NotAuthorized {
+            msg: "You're blocked from adding or liking shoes".to_string(),
+            caller,
+        });
+    }
+    if let PolicyAction::Like(shoe) = action {
+        if policy.no_self_like && shoe.owner == caller.to_string() {
+            return Err(Error::NotAuthorized {
+                msg: "You can't like your own shoe".to_string(),
+                caller,
+            });
+        }
+    }
+    if let Some(max_actions) = policy.max_actions_per_minute {
+        RATE_LIMITS.with(|service| {
+            let mut windows = service.borrow_mut();
+            windows.retain(|_, (start, _)| now.saturating_sub(*start) < RATE_LIMIT_WINDOW);
+            let (_, actions) = windows.entry(caller).or_insert((now, 0));
+            if *actions >= max_actions {
+                return Err(Error::RateLimited {
+                    msg: format!(
+                        "At most {} shoes can be added or liked per minute",
+                        max_actions
+                    ),
+                });
+            }
+            *actions += 1;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+// helper method to remove a shoe along with everything stored about it. used in delete_shoe/merge_shoes
+fn _remove_shoe(id: u64) -> Option<Shoe> {
+    let shoe = SHOE_STORAGE.with(|service| service.borrow_mut().remove(&id))?;
+    _update_indexes(Some(&shoe), None);
+    HISTORY_STORAGE.with(|service| service.borrow_mut().remove(&id));
+    SHOE_IMAGES.with(|service| service.borrow_mut().remove(&id));
+    SHOE_TAGS.with(|service| service.borrow_mut().remove(&id));
+    SHOE_RETURN_POLICIES.with(|service| service.borrow_mut().remove(&id));
+    _clear_reports(id);
+    _clear_comments(id);
+    _clear_reviews(id);
+    _clear_likes(id);
+    FEATURED_SHOES.with(|service| service.borrow_mut().remove(&id));
+    Some(shoe)
+}
+
+// helper method to record an action of the caller in the audit log
+fn _audit(action: &str, shoe_id: Option<u64>) {
+    _audit_as(caller(), action, shoe_id, time());
+}
+
+// helper method to record an action of `actor` at `now` in the audit log. only the latest
+// MAX_AUDIT_LOG_LEN entries are kept. used in _audit
+fn _audit_as(actor: Principal, action: &str, shoe_id: Option<u64>, now: u64) {
+    let id = AUDIT_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment audit id counter");
+    let entry = AuditEntry {
+        id,
+        actor,
+        action: action.to_string(),
+        shoe_id,
+        timestamp: now,
+    };
+    AUDIT_LOG.with(|service| {
+        let mut log = service.borrow_mut();
+        log.insert(id, entry);
+        if log.len() > MAX_AUDIT_LOG_LEN {
+            if let Some((oldest, _)) = log.iter().next() {
+                log.remove(&oldest);
+            }
+        }
+    });
+}
+
+// helper method to list who liked a shoe and when. used in get_likers/_interactors_of/like_rate
+fn _shoe_likes(id: u64) -> Vec<(Principal, u64)> {
+    SHOE_LIKES.with(|service| {
+        service
+            .borrow()
+            .range((id, StorablePrincipal::default())..)
+            .take_while(|((shoe_id, _), _)| *shoe_id == id)
+            .map(|((_, liker), liked_at)| (liker.0, liked_at))
+            .collect()
+    })
+}
+
+// helper method to remove the likes of a deleted shoe, taking them off their likers' counts
+fn _clear_likes(id: u64) {
+    for (liker, _) in _shoe_likes(id) {
+        SHOE_LIKES.with(|service| service.borrow_mut().remove(&(id, StorablePrincipal(liker))));
+        _decrement_likes_given(liker);
+    }
+}
+
+// helper method to take one like off the number of likes a principal has given. used in
+// _unlike_shoe_as and _clear_likes
+fn _decrement_likes_given(liker: Principal) {
+    LIKES_GIVEN.with(|service| {
+        let mut likes_given = service.borrow_mut();
+        let key = StorablePrincipal(liker);
+        match likes_given.get(&key).unwrap_or(0) {
+            0 | 1 => likes_given.remove(&key),
+            count => likes_given.insert(key, count - 1),
+        };
+    });
+}
+
+// helper method to move the likers kept in the shoes' old `liked_by` field to SHOE_LIKES.
+// the shoes are read through a second view of their memory that only decodes the likes, and
+// are stored again without the field so a later upgrade doesn't move them twice. used in post_upgrade
+fn _migrate_legacy_likes() {
+    let legacy: StableBTreeMap<u64, LegacyShoeLikes, Memory> =
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))));
+    let to_migrate: Vec<LegacyShoeLikes> = legacy
+        .iter()
+        .map(|(_, likes)| likes)
+        .filter(|likes| {
+            likes
+                .liked_by
+                .as_ref()
+                .is_some_and(|liked_by| !liked_by.is_empty())
+        })
+        .collect();
+    for likes in to_migrate {
+        for liker in likes.liked_by.unwrap_or_default() {
+            SHOE_LIKES.with(|service| {
+                service
+                    .borrow_mut()
+                    .insert((likes.id, StorablePrincipal(liker)), likes.created_at)
+            });
+        }
+        if let Some(shoe) = _get_shoe(&likes.id) {
+            SHOE_STORAGE.with(|service| service.borrow_mut().insert(shoe.id, shoe));
+        }
+    }
+}
+
+// helper method to list the reviews of a shoe. used in get_reviews/get_shoes_with_ratings
+fn _shoe_reviews(id: u64) -> Vec<Review> {
+    REVIEW_STORAGE.with(|service| {
+        service
+            .borrow()
+            .range((id, StorablePrincipal::default())..)
+            .take_while(|((shoe_id, _), _)| *shoe_id == id)
+            .map(|(_, review)| review)
+            .collect()
+    })
+}
+
+// helper method to remove the reviews of a deleted shoe
+fn _clear_reviews(id: u64) {
+    for review in _shoe_reviews(id) {
+        REVIEW_STORAGE.with(|service| {
+            service
+                .borrow_mut()
+                .remove(&(id, StorablePrincipal(review.author)))
+        });
+    }
+}
+
+// helper method to summarize a shoe for listing grids. used in get_shoes_with_ratings
+fn _to_summary(shoe: &Shoe) -> ShoeSummary {
+    ShoeSummary {
+        id: shoe.id,
+        name: shoe.name.clone(),
+        category: _category_of(shoe),
+        price: shoe.price,
+        sale_price: shoe.sale_price,
+        shoe_url: shoe.shoe_url.clone(),
+        like: shoe.like,
+    }
+}
+
+// helper method to rank the top `limit` trending shoes. used in get_trending_shoes/heartbeat
+fn _compute_trending(limit: u64, now: u64) -> Vec<Shoe> {
+    let weights = get_trending_weights();
+    let mut scored: Vec<(f64, Shoe)> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| _is_listed(item))
+            .map(|(_, item)| (_trending_score(&item, now, weights), item))
+            .collect()
+    });
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored
+        .into_iter()
+        .take(limit as usize)
+        .map(|(_, item)| item)
+        .collect()
+}
+
+// helper method to replace the trending cache with a freshly computed ranking
+fn _refresh_trending_cache(now: u64) {
+    let ids = _compute_trending(TRENDING_CACHE_SIZE, now)
+        .iter()
+        .map(|shoe| shoe.id)
+        .collect();
+    TRENDING_CACHE.with(|cache| {
+        *cache.borrow_mut() = Some(TrendingCache {
+            computed_at: now,
+            ids,
+        })
+    });
+}
+
+// helper method to add a comment from the given author at `now`. used in add_comment
+fn _add_comment_as(
+    shoe_id: u64,
+    text: String,
+    author: Principal,
+    now: u64,
+) -> Result<Comment, Error> {
+    let shoe = _get_shoe(&shoe_id).ok_or(Error::NotFound {
+        msg: format!("Shoe with ID {} not found. Cannot comment.", shoe_id),
+    })?;
+    _ensure_listed(&shoe)?;
+    let text = text.trim().to_string();
+    if text.is_empty() || text.len() > MAX_COMMENT_LEN {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A comment must be between 1 and {} bytes long",
+                MAX_COMMENT_LEN
+            ),
+        });
+    }
+    let id = COMMENT_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment comment id counter");
+    let comment = Comment {
+        id,
+        shoe_id,
+        author,
+        text,
+        created_at: now,
+    };
+    COMMENT_STORAGE.with(|service| service.borrow_mut().insert(comment.id, comment.clone()));
+    Ok(comment)
+}
+
+// helper method to delete a comment on behalf of the given caller. used in delete_comment
+fn _delete_comment_as(
+    comment_id: u64,
+    caller: Principal,
+    is_admin: bool,
+) -> Result<Comment, Error> {
+    let comment = COMMENT_STORAGE
+        .with(|service| service.borrow().get(&comment_id))
+        .ok_or(Error::NotFound {
+            msg: format!("a comment with id={} not found", comment_id),
+        })?;
+    if comment.author != caller && !is_admin {
+        return Err(Error::NotAuthorized {
+            msg: format!(
+                "You're not the author of the comment with id={}",
+                comment_id
+            ),
+            caller,
+        });
+    }
+    COMMENT_STORAGE.with(|service| service.borrow_mut().remove(&comment_id));
+    Ok(comment)
+}
+
+// helper method to remove the comments of a deleted shoe
+fn _clear_comments(shoe_id: u64) {
+    for comment in get_comments(shoe_id) {
+        COMMENT_STORAGE.with(|service| service.borrow_mut().remove(&comment.id));
+    }
+}
+
+// helper method to add a shoe to, or remove it from, the featured shoes. used in set_featured
+fn _set_featured(id: u64, featured: bool) -> Result<u64, Error> {
+    if _get_shoe(&id).is_none() {
+        return Err(Error::NotFound {
+            msg: format!("a shoe with id={} not found", id),
+        });
+    }
+    FEATURED_SHOES.with(|service| {
+        let mut storage = service.borrow_mut();
+        if featured {
+            storage.insert(id, ());
+        } else {
+            storage.remove(&id);
+        }
+    });
+    Ok(id)
+}
+
+// helper method to feature or unfeature each of `ids`, one result per id. used in set_featured_bulk
+fn _set_featured_bulk(ids: Vec<u64>, featured: bool) -> Vec<Result<u64, Error>> {
+    ids.into_iter()
+        .map(|id| _set_featured(id, featured))
+        .collect()
+}
+
+// helper method to pick the `count` shoes of the rotation window at `now`. used in get_rotating_featured
+fn _rotating_window(pool: Vec<Shoe>, count: u64, now: u64, interval_secs: u64) -> Vec<Shoe> {
+    if pool.is_empty() {
+        return pool;
+    }
+    let window = now / 1_000_000_000 / interval_secs.max(1);
+    let start = (window % pool.len() as u64) as usize;
+    let count = (count as usize).min(pool.len());
+    pool.iter()
+        .cycle()
+        .skip(start)
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+// helper method to derive the state of a shoe. used in get_shoes_by_status
+fn _shoe_status(shoe: &Shoe) -> ShoeStatus {
+    if shoe.deleted_at.is_some() {
+        ShoeStatus::Deleted
+    } else if !_is_published(shoe) {
+        ShoeStatus::Draft
+    } else if shoe.quantity <= 0 {
+        ShoeStatus::OutOfStock
+    } else {
+        ShoeStatus::Active
+    }
+}
+
+// helper method to get the balance a seller has accrued from sales. used in get_my_balance
+fn _balance_of(seller: Principal) -> u64 {
+    LEDGER.with(|service| {
+        service
+            .borrow()
+            .get(&StorablePrincipal(seller))
+            .unwrap_or(0)
+    })
+}
+
+// helper method to add the total of a sale to a seller's balance. used in purchase_shoe
+fn _credit_seller(seller: Principal, amount: u64) {
+    LEDGER.with(|service| {
+        let mut ledger = service.borrow_mut();
+        let key = StorablePrincipal(seller);
+        let balance = ledger.get(&key).unwrap_or(0);
+        ledger.insert(key, balance.saturating_add(amount));
+    });
+}
+
+// helper method to hold `amount` units of a shoe for a buyer from `now`. used in reserve_shoe
+fn _reserve_shoe_as(
+    id: u64,
+    amount: i16,
+    buyer: Principal,
+    now: u64,
+) -> Result<Reservation, Error> {
+    let mut shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("couldn't reserve a shoe with id={}. shoe not found", id),
+    })?;
+    _ensure_listed(&shoe)?;
+    if amount <= 0 {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid reservation amount {}", amount),
+        });
+    }
+    if amount > shoe.quantity {
+        return Err(Error::InsufficientStock {
+            msg: format!(
+                "Only {} units of the shoe with id={} are in stock",
+                shoe.quantity, id
+            ),
+        });
+    }
+    shoe.quantity -= amount;
+    _insert_stock_change(&shoe);
+
+    let reservation_id = RESERVATION_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment reservation id counter");
+    let reservation = Reservation {
+        id: reservation_id,
+        shoe_id: id,
+        buyer,
+        amount,
+        expires_at: now + RESERVATION_TTL,
+    };
+    RESERVATION_STORAGE.with(|service| {
+        service
+            .borrow_mut()
+            .insert(reservation.id, reservation.clone())
+    });
+    Ok(reservation)
+}
+
+// helper method to release one of a buyer's reservations. used in release_reservation
+fn _release_reservation_as(reservation_id: u64, caller: Principal) -> Result<Reservation, Error> {
+    let reservation = RESERVATION_STORAGE
+        .with(|service| service.borrow().get(&reservation_id))
+        .ok_or(Error::NotFound {
+            msg: format!("a reservation with id={} not found", reservation_id),
+        })?;
+    if reservation.buyer != caller {
+        return Err(Error::NotAuthorized {
+            msg: format!(
+                "You're not the buyer of the reservation with id={}",
+                reservation_id
+            ),
+            caller,
+        });
+    }
+    _release_reservation(&reservation);
+    Ok(reservation)
+}
+
+// helper method to get a buyer's reservations that haven't been released yet. used in get_my_reservations
+fn _reservations_of(buyer: Principal) -> Vec<Reservation> {
+    RESERVATION_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, reservation)| reservation.buyer == buyer)
+            .map(|(_, reservation)| reservation)
+            .collect()
+    })
+}
+
+// helper method to get a buyer's unexpired reservations of a shoe at `now`. used in _purchase_shoe_as
+fn _active_reservations(shoe_id: u64, buyer: Principal, now: u64) -> Vec<Reservation> {
+    RESERVATION_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, reservation)| {
+                reservation.shoe_id == shoe_id
+                    && reservation.buyer == buyer
+                    && reservation.expires_at > now
+            })
+            .map(|(_, reservation)| reservation)
+            .collect()
+    })
+}
+
+// helper method to schedule the recurring release of expired reservations. used in init/post_upgrade
+fn _start_reservation_cleanup() {
+    ic_cdk_timers::set_timer_interval(RESERVATION_CLEANUP_INTERVAL, || {
+        _release_expired_reservations(time());
+    });
+}
+
+// helper method to release every reservation that expired by `now`, returning how many were released
+fn _release_expired_reservations(now: u64) -> u64 {
+    let expired: Vec<Reservation> = RESERVATION_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, reservation)| reservation.expires_at <= now)
+            .map(|(_, reservation)| reservation)
+            .collect()
+    });
+    for reservation in &expired {
+        _release_reservation(reservation);
+    }
+    expired.len() as u64
+}
+
+// helper method to remove a reservation and return its units to the shoe's stock
+fn _release_reservation(reservation: &Reservation) {
+    RESERVATION_STORAGE.with(|service| service.borrow_mut().remove(&reservation.id));
+    if let Some(mut shoe) = _get_shoe(&reservation.shoe_id) {
+        shoe.quantity = shoe.quantity.saturating_add(reservation.amount);
+        _insert_stock_change(&shoe);
+    }
+}
+
+// helper method to score a shoe for get_trending_shoes
+fn _trending_score(shoe: &Shoe, now: u64, weights: TrendingWeights) -> f64 {
+    let age_in_seconds = now.saturating_sub(shoe.created_at.as_nanos()) / 1_000_000_000;
+    (shoe.like as f64).powf(weights.like_weight)
+        / (age_in_seconds as f64 + 1.0).powf(weights.recency_weight)
+}
+
+// helper method to summarise the listings of an owner. used in my_dashboard
+fn _dashboard(owner: &str) -> SellerDashboard {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage.iter().filter(|(_, item)| item.owner == owner).fold(
+            SellerDashboard::default(),
+            |mut dashboard, (_, item)| {
+                let units = item.quantity.max(0) as u64;
+                dashboard.listing_count += 1;
+                dashboard.total_units += units;
+                dashboard.total_likes += item.like as u64;
+                if item.quantity <= 0 {
+                    dashboard.out_of_stock += 1;
+                }
+                dashboard.inventory_value += item.price.max(0) as u64 * units;
+                dashboard
+            },
+        )
+    })
+}
+
+// helper method to record a like from the given caller. used in like_shoe
+fn _like_shoe_as(id: u64, caller: Principal, now: u64) -> Result<Shoe, Error> {
+    match _get_shoe(&id) {
+        Some(mut likes_shoe) => {
+            _ensure_listed(&likes_shoe)?;
+            _check_policy(PolicyAction::Like(&likes_shoe), caller, now)?;
+            let key = (id, StorablePrincipal(caller));
+            // users can only like a shoe once
+            if SHOE_LIKES.with(|service| service.borrow().contains_key(&key)) {
+                return Err(Error::AlreadyLiked {
+                    msg: format!(
+                        "Shoe with ID {} has already been liked by caller: {}.",
+                        id, caller
+                    ),
+                });
+            }
+            if likes_shoe.like as u64 >= get_like_limit() {
+                return Err(Error::LikeLimitReached {
+                    msg: format!("Shoe with ID {} has reached the like limit.", id),
+                });
+            }
+            let cooldown = get_like_cooldown() * 1_000_000_000;
+            let last_like_at =
+                LAST_LIKE_AT.with(|service| service.borrow().get(&StorablePrincipal(caller)));
+            if last_like_at.is_some_and(|last_like_at| now.saturating_sub(last_like_at) < cooldown)
+            {
+                return Err(Error::RateLimited {
+                    msg: format!(
+                        "Likes are limited to one every {} seconds",
+                        get_like_cooldown()
+                    ),
+                });
+            }
+            likes_shoe.like += 1;
+            do_insert(&likes_shoe);
+            SHOE_LIKES.with(|service| service.borrow_mut().insert(key, now));
+            LAST_LIKE_AT
+                .with(|service| service.borrow_mut().insert(StorablePrincipal(caller), now));
+            LIKES_GIVEN.with(|service| {
+                let mut likes_given = service.borrow_mut();
+                let key = StorablePrincipal(caller);
+                let count = likes_given.get(&key).unwrap_or(0);
+                likes_given.insert(key, count + 1);
+            });
+            Ok(likes_shoe.clone())
+        }
+        None => Err(Error::NotFound {
+            msg: format!("Shoe with ID {} not found. Cannot like.", id),
+        }),
+    }
+}
+
+// helper method to validate a partial update and apply it to a shoe. used in patch_shoe
+fn _apply_update(shoe: &mut Shoe, update: ShoeUpdate) -> Result<(), Error> {
+    if update.price.is_some_and(|price| !is_valid_price(price)) {
+        return Err(Error::ValidationError {
+            msg: "Invalid price value".to_string(),
+        });
+    }
+    if let Some(price) = update.price {
+        validate_min_price(price)?;
+    }
+    if let Some(quantity) = update.quantity {
+        validate_max_quantity(quantity)?;
+    }
+    if update
+        .quantity
+        .is_some_and(|quantity| !is_valid_quantity(quantity))
+    {
+        return Err(Error::ValidationError {
+            msg: "Invalid quantity value".to_string(),
+        });
+    }
+    if let Some(name) = update.name {
+        shoe.name = name;
+    }
+    if let Some(brand) = update.brand {
+        validate_brand(&brand)?;
+        shoe.brand = normalize_brand(&brand);
+    }
+    if let Some(category) = update.category {
+        validate_category(&category)?;
+        shoe.category = Some(normalize_category(&category));
+    }
+    if let Some(size) = update.size {
+        shoe.shoe_size = parse_shoe_size(&size);
+        shoe.size = size;
+    }
+    if let Some(price) = update.price {
+        shoe.price = price;
+    }
+    if let Some(shoe_url) = update.shoe_url {
+        shoe.shoe_url = shoe_url;
+    }
+    if let Some(image_hash) = update.image_hash {
+        shoe.image_hash = Some(image_hash);
+    }
+    if let Some(quantity) = update.quantity {
+        shoe.quantity = quantity;
+    }
+    Ok(())
+}
+
+// helper method to read a shoe's category, empty for shoes stored before categories existed
+fn _category_of(shoe: &Shoe) -> String {
+    normalize_category(shoe.category.as_deref().unwrap_or_default())
+}
+
+// helper method to remove a like from the given caller. used in unlike_shoe
+fn _unlike_shoe_as(id: u64, caller: Principal) -> Result<Shoe, Error> {
+    let mut shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("Shoe with ID {} not found. Cannot unlike.", id),
+    })?;
+    SHOE_LIKES
+        .with(|service| {
+            service
+                .borrow_mut()
+                .remove(&(id, StorablePrincipal(caller)))
+        })
+        .ok_or(Error::NotLiked {
+            msg: format!(
+                "Shoe with ID {} has not been liked by caller: {}.",
+                id, caller
+            ),
+        })?;
+    shoe.like = shoe.like.saturating_sub(1);
+    do_insert(&shoe);
+    _decrement_likes_given(caller);
+    Ok(shoe)
+}
+
+// helper method to sell `amount` units of a shoe to a buyer at `now`. used in purchase_shoe
+fn _purchase_shoe_as(id: u64, amount: i16, buyer: Principal, now: u64) -> Result<Shoe, Error> {
+    let mut shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("couldn't purchase a shoe with id={}. shoe not found", id),
+    })?;
+    _ensure_listed(&shoe)?;
+    if amount <= 0 {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid purchase amount {}", amount),
+        });
+    }
+    // units the buyer reserved are already out of stock, so they count towards the purchase
+    let reservations = _active_reservations(id, buyer, now);
+    let reserved: i32 = reservations
+        .iter()
+        .map(|reservation| reservation.amount as i32)
+        .sum();
+    let available = shoe.quantity as i32 + reserved;
+    if amount as i32 > available {
+        return Err(Error::InsufficientStock {
+            msg: format!(
+                "Only {} units of the shoe with id={} are available",
+                available, id
+            ),
+        });
+    }
+    // the purchase consumes the buyer's reservations; reserved units it doesn't use go back to stock
+    for reservation in &reservations {
+        RESERVATION_STORAGE.with(|service| service.borrow_mut().remove(&reservation.id));
+    }
+    shoe.quantity = (available - amount as i32).min(i16::MAX as i32) as i16;
+    shoe.sold = Some(shoe.sold.unwrap_or(0) + amount as u64);
+    _insert_stock_change(&shoe);
+    // a shoe on sale is charged its sale price
+    let unit_price = shoe.sale_price.unwrap_or(shoe.price);
+
+    let purchase_id = PURCHASE_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("cannot increment purchase id counter");
+    let purchase = Purchase {
+        id: purchase_id,
+        buyer,
+        shoe_id: id,
+        amount,
+        unit_price,
+        total: unit_price.max(0) as u64 * amount as u64,
+        timestamp: now,
+    };
+    if let Ok(seller) = Principal::from_text(&shoe.owner) {
+        _credit_seller(seller, purchase.total);
+    }
+    PURCHASE_STORAGE.with(|service| service.borrow_mut().insert(purchase.id, purchase));
+    Ok(shoe)
+}
+
+// helper method to list the purchases made by a buyer. used in get_my_purchases
+fn _purchases_of(buyer: Principal) -> Vec<Purchase> {
+    PURCHASE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, purchase)| purchase.buyer == buyer)
+            .map(|(_, purchase)| purchase)
+            .collect()
+    })
+}
+
+// helper method to list an owner's shoes at or below their low-stock threshold. used in get_low_stock_alerts
+fn _low_stock_alerts(owner: &str) -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                item.owner == owner
+                    && item
+                        .low_stock_threshold
+                        .is_some_and(|threshold| item.quantity <= threshold)
+            })
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// helper method to check a sale price against a shoe's price and the price floor. used in set_sale_price
+fn _validate_sale_price(shoe: &Shoe, sale_price: i16) -> Result<(), Error> {
+    if sale_price <= 0 || sale_price >= shoe.price {
+        return Err(Error::ValidationError {
+            msg: format!(
+                "A sale price must be positive and below the price of {}",
+                shoe.price
+            ),
+        });
+    }
+    validate_min_price(sale_price)
+}
+
+// helper method to move every shoe of an owner to a new owner at `now`, returning the ids of the
+// moved shoes. used in transfer_all_my_shoes
+fn _transfer_shoes(owner: &str, new_owner: Principal, now: u64) -> Vec<u64> {
+    let my_shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.owner == owner)
+            .map(|(_, item)| item)
+            .collect()
+    });
+    for mut shoe in my_shoes.iter().cloned() {
+        shoe.owner = new_owner.to_string();
+        shoe.updated_at = Some(Timestamp(now));
+        do_insert(&shoe);
+    }
+    my_shoes.iter().map(|shoe| shoe.id).collect()
+}
+
+// helper method to get the image urls of a shoe. used in get_shoe_images and the image endpoints
+fn _get_images(id: u64) -> Vec<String> {
+    SHOE_IMAGES.with(|service| service.borrow().get(&id).unwrap_or_default().0)
+}
+
+// helper method to replace the image urls of a shoe, dropping the entry when there are none.
+// used in add_shoe, update_shoe and the image endpoints
+fn _set_images(id: u64, images: Vec<String>) {
+    SHOE_IMAGES.with(|service| {
+        let mut storage = service.borrow_mut();
+        if images.is_empty() {
+            storage.remove(&id);
+        } else {
+            storage.insert(id, ImageList(images));
+        }
+    });
+}
+
+// helper method to get the tags of a shoe. used in get_shoe_tags and full_text_search
+fn _get_tags(id: u64) -> Vec<String> {
+    SHOE_TAGS.with(|service| service.borrow().get(&id).unwrap_or_default().0)
+}
+
+// helper method to replace the tags of a shoe, dropping the entry when there are none.
+// used in set_shoe_tags
+fn _set_tags(id: u64, tags: Vec<String>) {
+    SHOE_TAGS.with(|service| {
+        let mut storage = service.borrow_mut();
+        if tags.is_empty() {
+            storage.remove(&id);
+        } else {
+            storage.insert(id, TagList(tags));
+        }
+    });
+}
+
+// helper method to list the shoes never updated since `older_than_secs` before `now`. used in get_stale_shoes
+fn _stale_shoes(older_than_secs: u64, now: u64) -> Vec<Shoe> {
+    let cutoff = now.saturating_sub(older_than_secs.saturating_mul(1_000_000_000));
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.updated_at.is_none() && item.created_at.as_nanos() < cutoff)
+            .map(|(_, item)| item.clone())
+            .collect()
+    })
+}
+
+// helper method to set or clear a shoe's own return policy. used in set_shoe_return_policy
+fn _set_return_policy(id: u64, return_policy: Option<String>) -> Result<(), Error> {
+    match return_policy {
+        Some(return_policy) => {
+            validate_return_policy(&return_policy)?;
+            SHOE_RETURN_POLICIES
+                .with(|service| service.borrow_mut().insert(id, ReturnPolicy(return_policy)));
+        }
+        None => {
+            SHOE_RETURN_POLICIES.with(|service| service.borrow_mut().remove(&id));
+        }
+    }
+    Ok(())
+}
+
+// helper method to check whether a shoe shows up in the public listings: it isn't soft-deleted
+// and is published. used in get_shoes and the other public listings
+fn _is_listed(shoe: &Shoe) -> bool {
+    shoe.deleted_at.is_none() && _is_published(shoe)
+}
+
+// helper method to check whether a shoe is published rather than a draft. used in _is_listed
+fn _is_published(shoe: &Shoe) -> bool {
+    shoe.published.unwrap_or(true)
+}
+
+// helper method to list an owner's `limit` newest shoes. used in get_my_newest_shoes
+fn _newest_shoes_of(owner: &str, limit: u64) -> Vec<Shoe> {
+    let mut shoes = _sorted_shoes_of(owner, SortBy::CreatedAt, true);
+    shoes.truncate(limit.min(get_max_page_size()) as usize);
+    shoes
+}
+
+// helper method to list all of an owner's shoes sorted by `sort`. used in get_my_shoes_sorted/_newest_shoes_of
+fn _sorted_shoes_of(owner: &str, sort: SortBy, descending: bool) -> Vec<Shoe> {
+    let mut shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.owner == owner)
+            .map(|(_, item)| item)
+            .collect()
+    });
+    _sort_shoes(&mut shoes, sort, descending);
+    shoes
+}
+
+// helper method to list the listed shoes restocking after `now`, soonest first. used in get_upcoming_restocks
+fn _upcoming_restocks(now: u64) -> Vec<Shoe> {
+    let mut shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                _is_listed(item) && item.restock_date.is_some_and(|date| date > now)
+            })
+            .map(|(_, item)| item)
+            .collect()
+    });
+    shoes.sort_by_key(|shoe| shoe.restock_date);
+    shoes
+}
+
+// helper method to compare-and-set the quantity of a shoe owned by `caller`. used in cas_quantity
+fn _cas_quantity_as(
+    id: u64,
+    expected: i16,
+    new: i16,
+    caller: Principal,
+    now: u64,
+) -> Result<Shoe, Error> {
+    let mut shoe = _get_active_shoe_owned_by(id, caller)?;
+    // 0 is allowed so a sync can mark the shoe sold out
+    if new < 0 {
+        return Err(Error::ValidationError {
+            msg: "Invalid quantity value".to_string(),
+        });
+    }
+    validate_max_quantity(new)?;
+    if shoe.quantity != expected {
+        return Err(Error::QuantityMismatch {
+            msg: format!(
+                "The quantity of the shoe with id={} is {}, not {}",
+                id, shoe.quantity, expected
+            ),
+            current: shoe.quantity,
+        });
+    }
+    shoe.quantity = new;
+    shoe.updated_at = Some(Timestamp(now));
+    do_insert(&shoe);
+    Ok(shoe)
+}
+
+// helper method to list the distinct principals that liked or purchased an owner's shoes.
+// used in interactors_for_my_shoes
+fn _interactors_of(owner: &str) -> Vec<Principal> {
+    let owned_ids: std::collections::BTreeSet<u64> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.owner == owner)
+            .map(|(id, _)| id)
+            .collect()
+    });
+    let mut interactors: std::collections::BTreeSet<Principal> = std::collections::BTreeSet::new();
+    for id in &owned_ids {
+        interactors.extend(_shoe_likes(*id).into_iter().map(|(liker, _)| liker));
+    }
+    PURCHASE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        interactors.extend(
+            storage
+                .iter()
+                .filter(|(_, purchase)| owned_ids.contains(&purchase.shoe_id))
+                .map(|(_, purchase)| purchase.buyer),
+        );
+    });
+    interactors.into_iter().collect()
+}
+
+// helper method to get the first id past both the id counter and every stored id. used in reset_id_counter
+fn _next_free_id() -> u64 {
+    let current = ID_COUNTER.with(|counter| *counter.borrow().get());
+    SHOE_STORAGE
+        .with(|service| service.borrow().iter().map(|(id, _)| id + 1).max())
+        .unwrap_or(0)
+        .max(current)
+}
+
+// helper method to total the stock of the listed shoes. used in inventory_snapshot
+fn _inventory_snapshot(now: u64) -> InventorySnapshot {
+    let snapshot = InventorySnapshot {
+        taken_at: now,
+        ..Default::default()
+    };
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage.iter().filter(|(_, item)| _is_listed(item)).fold(
+            snapshot,
+            |mut snapshot, (_, item)| {
+                let units = item.quantity.max(0) as u64;
+                snapshot.listings += 1;
+                snapshot.total_units += units;
+                snapshot.total_value += item.price.max(0) as u64 * units;
+                snapshot
+            },
+        )
+    })
+}
+
+// helper method to move an owner's listed shoes from one category to another at `now`.
+// used in recategorize_my_shoes
+fn _recategorize(owner: &str, from: &str, to: &str, now: u64) -> Result<u64, Error> {
+    validate_category(to)?;
+    let (from, to) = (normalize_category(from), normalize_category(to));
+    let my_shoes: Vec<Shoe> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                item.owner == owner && item.deleted_at.is_none() && _category_of(item) == from
+            })
+            .map(|(_, item)| item)
+            .collect()
+    });
+    if from == to {
+        return Ok(my_shoes.len() as u64);
+    }
+    for mut shoe in my_shoes.iter().cloned() {
+        shoe.category = Some(to.clone());
+        shoe.updated_at = Some(Timestamp(now));
+        do_insert(&shoe);
+    }
+    Ok(my_shoes.len() as u64)
+}
+
+// helper method to reject changes while an admin has closed the store
+fn _ensure_store_open() -> Result<(), Error> {
+    if !is_store_open() {
+        return Err(Error::StoreClosed {
+            msg: "The store is closed for maintenance".to_string(),
+        });
+    }
+    Ok(())
+}
+
+// helper method to trap while the store is closed, for the endpoints that don't return an Error
+fn _trap_if_store_closed() {
+    if let Err(Error::StoreClosed { msg }) = _ensure_store_open() {
+        ic_cdk::trap(&msg);
+    }
+}
+
+// helper method to list an owner's shoes with their stock value, sorted by it. used in get_my_shoes_by_value
+fn _shoes_by_value_of(owner: &str, descending: bool) -> Vec<(Shoe, u64)> {
+    let mut shoes: Vec<(Shoe, u64)> = SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.owner == owner)
+            .map(|(_, item)| {
+                let value = item.price.max(0) as u64 * item.quantity.max(0) as u64;
+                (item, value)
+            })
+            .collect()
+    });
+    shoes.sort_by_key(|(_, value)| *value);
+    if descending {
+        shoes.reverse();
+    }
+    shoes
+}
+
+// helper method to pick one listed featured shoe from a timestamp. used in random_featured_shoe
+fn _pick_featured(now: u64) -> Option<Shoe> {
+    let mut pool = get_featured_shoes();
+    if pool.is_empty() {
+        return None;
+    }
+    // scramble the nanoseconds so calls close together don't pick neighbouring shoes
+    let seed = now.wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(31);
+    let index = (seed % pool.len() as u64) as usize;
+    Some(pool.swap_remove(index))
+}
+
+// helper method to list the shoes without an owner. used in get_orphan_shoes
+fn _orphan_shoes() -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.owner.trim().is_empty())
+            .map(|(_, item)| item)
+            .collect()
+    })
+}
+
+// helper method to give a shoe without an owner to `owner`. used in assign_orphan_owner
+fn _assign_orphan(id: u64, owner: Principal, now: u64) -> Result<Shoe, Error> {
+    let mut shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("a shoe with id={} not found", id),
+    })?;
+    if !shoe.owner.trim().is_empty() {
+        return Err(Error::ValidationError {
+            msg: format!("The shoe with id={} already has an owner", id),
+        });
+    }
+    if owner == Principal::anonymous() {
+        return Err(Error::ValidationError {
+            msg: "A shoe can't be assigned to the anonymous principal".to_string(),
+        });
+    }
+    shoe.owner = owner.to_string();
+    shoe.updated_at = Some(Timestamp(now));
+    do_insert(&shoe);
+    Ok(shoe)
+}
+
+// helper method to merge one of the caller's listings into another. used in merge_shoes
+fn _merge_shoes_as(
+    keep_id: u64,
+    remove_id: u64,
+    caller: Principal,
+    now: u64,
+) -> Result<Shoe, Error> {
+    if keep_id == remove_id {
+        return Err(Error::ValidationError {
+            msg: "A shoe can't be merged into itself".to_string(),
+        });
+    }
+    let mut kept = _get_active_shoe_owned_by(keep_id, caller)?;
+    let removed = _get_active_shoe_owned_by(remove_id, caller)?;
+    kept.quantity = kept
+        .quantity
+        .checked_add(removed.quantity)
+        .ok_or(Error::ValidationError {
+            msg: format!(
+                "Merging shoe with id={} into id={} overflows its quantity",
+                remove_id, keep_id
+            ),
+        })?;
+    validate_max_quantity(kept.quantity)?;
+    kept.sold = match (kept.sold, removed.sold) {
+        (None, None) => None,
+        (kept_sold, removed_sold) => Some(kept_sold.unwrap_or(0) + removed_sold.unwrap_or(0)),
+    };
+
+    // likes move over to the kept shoe. a liker who liked both shoes keeps their like on the
+    // removed one, which goes away with it in _remove_shoe
+    for (liker, liked_at) in _shoe_likes(remove_id) {
+        let key = (keep_id, StorablePrincipal(liker));
+        if !SHOE_LIKES.with(|service| service.borrow().contains_key(&key)) {
+            SHOE_LIKES.with(|service| {
+                let mut likes = service.borrow_mut();
+                likes.insert(key, liked_at);
+                likes.remove(&(remove_id, StorablePrincipal(liker)));
+            });
+        }
+    }
+    kept.like = _shoe_likes(keep_id).len() as u32;
+    kept.updated_at = Some(Timestamp(now));
+    do_insert(&kept);
+    _remove_shoe(remove_id);
+    Ok(kept)
+}
+
+// helper method to build the price points of a shoe up to `now`. used in price_history
+fn _price_history(id: u64, now: u64) -> Result<Vec<(u64, i16)>, Error> {
+    let shoe = get_shoe_by_id(id)?;
+    let changes: Vec<(u64, i16, i16)> = get_shoe_history(id)
+        .into_iter()
+        .filter(|entry| entry.field == "price")
+        .filter_map(|entry| {
+            let old_price = entry.old_value.parse::<i16>().ok()?;
+            let new_price = entry.new_value.parse::<i16>().ok()?;
+            Some((entry.timestamp, old_price, new_price))
+        })
+        .collect();
+    let first_price = changes
+        .first()
+        .map(|(_, old_price, _)| *old_price)
+        .unwrap_or(shoe.price);
+    let mut points = vec![(shoe.created_at.as_nanos(), first_price)];
+    points.extend(
+        changes
+            .into_iter()
+            .map(|(timestamp, _, new_price)| (timestamp, new_price)),
+    );
+    points.push((now, shoe.price));
+    Ok(points)
+}
+
+// helper method to list an owner's shoes in a category, ignoring case. used in get_my_shoes_in_category
+fn _shoes_in_category_of(owner: &str, category: &str) -> Vec<Shoe> {
+    let category = normalize_category(category);
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.owner == owner && _category_of(item) == category)
+            .map(|(_, item)| item)
+            .collect()
+    })
+}
+
+// helper method to build the payload listing a shoe again in another size. used in clone_shoe_with_size
+fn _size_variant_payload(source: Shoe, size: String, quantity: i16) -> ShoePayload {
+    ShoePayload {
+        schema_version: Some(CURRENT_SCHEMA_VERSION),
+        name: source.name,
+        brand: source.brand,
+        category: source.category,
+        size,
+        shoe_url: source.shoe_url,
+        price: source.price,
+        quantity,
+        ..Default::default()
+    }
+}
+
+// helper method to page through the audit entries of `actor`, oldest first. used in get_actions_by
+fn _actions_by(actor: Principal, offset: u64, limit: u64) -> Vec<AuditEntry> {
+    AUDIT_LOG.with(|service| {
+        let log = service.borrow();
+        log.iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| entry.actor == actor)
+            .skip(offset as usize)
+            .take(limit.min(get_max_page_size()) as usize)
+            .collect()
+    })
+}
+
+// helper method to list every shoe of an owner, drafts included. used in get_my_shoes
+fn _shoes_of(owner: &str) -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| item.owner == owner)
+            .map(|(_, item)| item)
+            .collect()
+    })
+}
+
+// helper method to publish or unpublish one of the caller's shoes at `now`. used in publish_shoe
+// and unpublish_shoe
+fn _set_published_as(id: u64, published: bool, caller: Principal, now: u64) -> Result<Shoe, Error> {
+    let mut shoe = _get_active_shoe_owned_by(id, caller)?;
+    if _is_published(&shoe) != published {
+        _mark_published(&mut shoe, published, now);
+        shoe.updated_at = Some(Timestamp(now));
+        do_insert(&shoe);
+        let action = if published {
+            "publish_shoe"
+        } else {
+            "unpublish_shoe"
+        };
+        _audit_as(caller, action, Some(id), now);
+    }
+    Ok(shoe)
+}
+
+// helper method to set whether a shoe is published, recording `now` as published_at when it goes
+// live. used in update_shoe and _set_published_as
+fn _mark_published(shoe: &mut Shoe, published: bool, now: u64) {
+    if published && !_is_published(shoe) {
+        shoe.published_at = Some(now);
+    }
+    shoe.published = Some(published);
+}
+
+// helper method to list an owner's shoes that aren't deleted, priced at `min_price` or more with
+// at most `max_likes` likes. used in get_my_underperformers
+fn _underperformers_of(owner: &str, min_price: i16, max_likes: u32) -> Vec<Shoe> {
+    SHOE_STORAGE.with(|service| {
+        let storage = service.borrow();
+        storage
+            .iter()
+            .filter(|(_, item)| {
+                item.owner == owner
+                    && item.deleted_at.is_none()
+                    && item.price >= min_price
+                    && item.like <= max_likes
+            })
+            .map(|(_, item)| item)
+            .collect()
+    })
+}
+
+// Helper function to check whether the caller is an admin (a controller of the canister)
+fn _is_admin() -> bool {
+    ic_cdk::api::is_controller(&caller())
+}
+
+// helper method to get a shoe owned by the caller. a missing shoe is always reported as
+// NotFound, and only an existing shoe owned by someone else as NotAuthorized
+fn _get_owned_shoe(id: u64) -> Result<Shoe, Error> {
+    _get_shoe_owned_by(id, caller())
+}
+
+// helper method to get a shoe owned by the given caller. used in _get_owned_shoe
+fn _get_shoe_owned_by(id: u64, caller: Principal) -> Result<Shoe, Error> {
+    let shoe = _get_shoe(&id).ok_or(Error::NotFound {
+        msg: format!("a shoe with id={} not found", id),
+    })?;
+    // Check if the caller is the owner of the shoe; if not, return an authorization error
+    if shoe.owner != caller.to_string() {
+        return Err(Error::NotAuthorized {
+            msg: format!("You're not the owner of the shoe with id={}", id),
+            caller,
+        });
+    }
+    Ok(shoe)
+}
+
+// helper method to find a shoe owned by the given owner identical to a payload. used in add_shoe
+fn _find_identical_shoe(payload: &ShoePayload, owner: Principal) -> Option<Shoe> {
+    let owner = owner.to_string();
+    let category = normalize_category(payload.category.as_deref().unwrap_or_default());
+    SHOE_STORAGE.with(|service| {
+        service.borrow().iter().map(|(_, item)| item).find(|item| {
+            item.owner == owner
+                && item.deleted_at.is_none()
+                && item.name.trim() == payload.name.trim()
+                && item.size.trim() == payload.size.trim()
+                && item.price == payload.price
+                && _category_of(item) == category
+                && item.sku.as_deref().unwrap_or_default()
+                    == normalize_sku(payload.sku.as_deref().unwrap_or_default())
+                && item.brand == payload.brand.as_deref().and_then(normalize_brand)
+                && item.condition.unwrap_or_default() == payload.condition.unwrap_or_default()
+                && _is_published(item) == payload.published.unwrap_or(true)
+        })
+    })
+}
+
+// helper method to add a payload's quantity to an identical existing shoe. used in add_shoe
+fn _merge_quantity(mut shoe: Shoe, quantity: i16) -> Result<Shoe, Error> {
+    shoe.quantity = shoe
+        .quantity
+        .checked_add(quantity)
+        .ok_or(Error::ValidationError {
+            msg: format!(
+                "Merging {} units into shoe with id={} overflows its quantity",
+                quantity, shoe.id
+            ),
+        })?;
+    validate_max_quantity(shoe.quantity)?;
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    Ok(shoe)
+}
+
+// helper method to add stock to a shoe owned by the caller. used in restock_bulk
+fn _restock_shoe(id: u64, amount: i16) -> Result<Shoe, Error> {
+    _ensure_store_open()?;
+    let mut shoe = _restocked_shoe(id, amount, caller())?;
+    shoe.updated_at = Some(Timestamp::now());
+    do_insert(&shoe);
+    Ok(shoe)
+}
+
+// helper method to check a restock by the given caller and return the restocked shoe. used in _restock_shoe
+fn _restocked_shoe(id: u64, amount: i16, caller: Principal) -> Result<Shoe, Error> {
+    let mut shoe = _get_active_shoe_owned_by(id, caller)?;
+    if amount <= 0 {
+        return Err(Error::ValidationError {
+            msg: format!("Invalid restock amount {} for shoe with id={}", amount, id),
+        });
+    }
+    shoe.quantity = shoe
+        .quantity
+        .checked_add(amount)
+        .ok_or(Error::ValidationError {
+            msg: format!(
+                "Restocking shoe with id={} by {} overflows its quantity",
+                id, amount
+            ),
+        })?;
+    validate_max_quantity(shoe.quantity)?;
+    Ok(shoe)
+}
+
+// helper method to reject actions of buyers, e.g. purchases and likes, on a shoe that isn't
+// listed. a draft is reported as NotFound, as it isn't public yet
+fn _ensure_listed(shoe: &Shoe) -> Result<(), Error> {
+    if shoe.deleted_at.is_some() {
+        return Err(Error::ShoeDeleted {
+            msg: format!("The shoe with id={} is deleted", shoe.id),
+            id: shoe.id,
+        });
+    }
+    if !_is_published(shoe) {
+        return Err(Error::NotFound {
+            msg: format!("The shoe with id={} is not published", shoe.id),
+        });
+    }
+    Ok(())
+}
+
+// helper method to get a shoe owned by the caller that can still be changed
+fn _get_active_owned_shoe(id: u64) -> Result<Shoe, Error> {
+    _get_active_shoe_owned_by(id, caller())
+}
+
+// helper method to get a shoe owned by the given caller that can still be changed. a soft-deleted
+// shoe is reported as ShoeDeleted until restore_shoe brings it back. used in _get_active_owned_shoe
+fn _get_active_shoe_owned_by(id: u64, caller: Principal) -> Result<Shoe, Error> {
+    let shoe = _get_shoe_owned_by(id, caller)?;
+    if shoe.deleted_at.is_some() {
+        return Err(Error::ShoeDeleted {
+            msg: format!(
+                "The shoe with id={} is deleted. Restore it before changing it",
+                id
+            ),
+            id,
+        });
+    }
+    Ok(shoe)
+}
+
+// Helper function to validate owner
+fn _validate_owner(shoe: &Shoe) -> bool {
+    shoe.owner == caller().to_string()
 }
 
 // need this to generate candid
 ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_shoe_owner_returns_the_stored_owner() {
+        let owner = Principal::from_text("2vxsx-fae").unwrap();
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            ..Default::default()
+        });
+        assert_eq!(get_shoe_owner(1).ok(), Some(owner));
+    }
+
+    #[test]
+    fn get_shoe_owner_fails_for_a_missing_shoe() {
+        assert!(matches!(get_shoe_owner(1), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn restock_adds_to_the_owners_stock() {
+        let owner = Principal::from_text("2vxsx-fae").unwrap();
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            quantity: 3,
+            ..Default::default()
+        });
+        let shoe = _restocked_shoe(1, 4, owner).ok().unwrap();
+        assert_eq!(shoe.quantity, 7);
+    }
+
+    #[test]
+    fn restock_rejects_bad_requests() {
+        let owner = Principal::from_text("2vxsx-fae").unwrap();
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            quantity: i16::MAX,
+            ..Default::default()
+        });
+        assert!(matches!(
+            _restocked_shoe(2, 1, owner),
+            Err(Error::NotFound { .. })
+        ));
+        assert!(matches!(
+            _restocked_shoe(1, 1, Principal::management_canister()),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(matches!(
+            _restocked_shoe(1, 0, owner),
+            Err(Error::ValidationError { .. })
+        ));
+        assert!(matches!(
+            _restocked_shoe(1, 1, owner),
+            Err(Error::ValidationError { .. })
+        ));
+    }
+
+    #[test]
+    fn convert_price_rounds_half_up() {
+        assert_eq!(convert_price(100, 10_000), 100);
+        assert_eq!(convert_price(3, 5_000), 2);
+        assert_eq!(convert_price(1, 4_999), 0);
+        assert_eq!(convert_price(100, 0), 0);
+    }
+
+    #[test]
+    fn convert_price_saturates_on_overflow() {
+        assert_eq!(convert_price(u64::MAX, 20_000), u64::MAX);
+    }
+
+    #[test]
+    fn get_shoes_created_between_is_inclusive() {
+        for (id, created_at) in [(1, 10), (2, 20), (3, 30)] {
+            do_insert(&Shoe {
+                id,
+                created_at: Timestamp(created_at),
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = get_shoes_created_between(20, 30)
+            .iter()
+            .map(|shoe| shoe.id)
+            .collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert!(get_shoes_created_between(30, 10).is_empty());
+    }
+
+    #[test]
+    fn trending_score_decays_with_age() {
+        let shoe = Shoe {
+            like: 10,
+            created_at: Timestamp(0),
+            ..Default::default()
+        };
+        assert_eq!(_trending_score(&shoe, 0, TrendingWeights::default()), 10.0);
+        assert_eq!(
+            _trending_score(&shoe, 4_000_000_000, TrendingWeights::default()),
+            2.0
+        );
+        // a shoe stamped after `now` is treated as brand new
+        let future = Shoe {
+            like: 3,
+            created_at: Timestamp(5_000_000_000),
+            ..Default::default()
+        };
+        assert_eq!(_trending_score(&future, 0, TrendingWeights::default()), 3.0);
+    }
+
+    #[test]
+    fn parse_shoe_size_reads_the_system_on_either_side() {
+        assert_eq!(
+            parse_shoe_size("US 10.5"),
+            Some(ShoeSize {
+                value: 10.5,
+                system: SizeSystem::US
+            })
+        );
+        assert_eq!(
+            parse_shoe_size("eu44"),
+            Some(ShoeSize {
+                value: 44.0,
+                system: SizeSystem::EU
+            })
+        );
+        assert_eq!(
+            parse_shoe_size("9 UK"),
+            Some(ShoeSize {
+                value: 9.0,
+                system: SizeSystem::UK
+            })
+        );
+    }
+
+    #[test]
+    fn parse_shoe_size_leaves_unrecognised_sizes_unstructured() {
+        assert_eq!(parse_shoe_size("10"), None);
+        assert_eq!(parse_shoe_size("US 0"), None);
+        assert_eq!(parse_shoe_size("XL"), None);
+        assert_eq!(parse_shoe_size(""), None);
+    }
+
+    #[test]
+    fn dashboard_sums_only_the_owners_listings() {
+        let owner = Principal::from_text("2vxsx-fae").unwrap().to_string();
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.clone(),
+            price: 50,
+            quantity: 2,
+            like: 3,
+            ..Default::default()
+        });
+        do_insert(&Shoe {
+            id: 2,
+            owner: owner.clone(),
+            price: 80,
+            quantity: 0,
+            like: 1,
+            ..Default::default()
+        });
+        do_insert(&Shoe {
+            id: 3,
+            owner: Principal::management_canister().to_string(),
+            price: 10,
+            quantity: 9,
+            ..Default::default()
+        });
+        let dashboard = _dashboard(&owner);
+        assert_eq!(dashboard.listing_count, 2);
+        assert_eq!(dashboard.total_units, 2);
+        assert_eq!(dashboard.total_likes, 4);
+        assert_eq!(dashboard.out_of_stock, 1);
+        assert_eq!(dashboard.inventory_value, 100);
+    }
+
+    #[test]
+    fn likes_stop_at_the_like_limit() {
+        LIKE_LIMIT.with(|cell| cell.borrow_mut().set(2)).ok();
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        assert!(_like_shoe_as(1, Principal::from_slice(&[1]), 0).is_ok());
+        assert!(matches!(
+            _like_shoe_as(1, Principal::from_slice(&[1]), 0),
+            Err(Error::AlreadyLiked { .. })
+        ));
+        assert!(_like_shoe_as(1, Principal::from_slice(&[2]), 0).is_ok());
+        assert!(matches!(
+            _like_shoe_as(1, Principal::from_slice(&[3]), 0),
+            Err(Error::LikeLimitReached { .. })
+        ));
+        assert_eq!(_get_shoe(&1).unwrap().like, 2);
+    }
+
+    #[test]
+    fn apply_update_changes_only_the_provided_fields() {
+        let mut shoe = Shoe {
+            name: "Air Max".to_string(),
+            size: "US 10".to_string(),
+            price: 120,
+            quantity: 3,
+            ..Default::default()
+        };
+        let update = ShoeUpdate {
+            size: Some("EU 44".to_string()),
+            price: Some(99),
+            ..Default::default()
+        };
+        assert!(_apply_update(&mut shoe, update).is_ok());
+        assert_eq!(shoe.name, "Air Max");
+        assert_eq!(shoe.size, "EU 44");
+        assert_eq!(shoe.shoe_size, parse_shoe_size("EU 44"));
+        assert_eq!(shoe.price, 99);
+        assert_eq!(shoe.quantity, 3);
+    }
+
+    #[test]
+    fn apply_update_rejects_invalid_values_without_changes() {
+        let mut shoe = Shoe {
+            name: "Air Max".to_string(),
+            price: 120,
+            ..Default::default()
+        };
+        let update = ShoeUpdate {
+            name: Some("Renamed".to_string()),
+            quantity: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            _apply_update(&mut shoe, update),
+            Err(Error::ValidationError { .. })
+        ));
+        assert_eq!(shoe.name, "Air Max");
+    }
+
+    #[test]
+    fn shoes_with_blank_image_urls_are_missing_an_image() {
+        for (id, shoe_url) in [(1, ""), (2, "   "), (3, "https://example.com/air-max")] {
+            do_insert(&Shoe {
+                id,
+                shoe_url: shoe_url.to_string(),
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = get_shoes_missing_image()
+            .iter()
+            .map(|shoe| shoe.id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn prices_below_the_floor_are_rejected() {
+        MIN_PRICE.with(|cell| cell.borrow_mut().set(50)).ok();
+        assert!(validate_min_price(50).is_ok());
+        assert!(matches!(
+            validate_min_price(49),
+            Err(Error::ValidationError { .. })
+        ));
+        let mut shoe = Shoe {
+            price: 120,
+            ..Default::default()
+        };
+        let update = ShoeUpdate {
+            price: Some(40),
+            ..Default::default()
+        };
+        assert!(_apply_update(&mut shoe, update).is_err());
+        assert_eq!(shoe.price, 120);
+    }
+
+    // the shoe as stored before any of the fields added since were there
+    #[derive(candid::CandidType, Serialize)]
+    struct BaselineShoe {
+        owner: String,
+        id: u64,
+        name: String,
+        size: String,
+        shoe_url: String,
+        price: i16,
+        quantity: i16,
+        like: u32,
+        liked_by: Vec<Principal>,
+        created_at: u64,
+        updated_at: Option<u64>,
+    }
+
+    #[test]
+    fn shoes_stored_before_categories_still_decode() {
+        let stored = BaselineShoe {
+            owner: Principal::anonymous().to_string(),
+            id: 7,
+            name: "Air Max".to_string(),
+            size: "US 10".to_string(),
+            shoe_url: "https://example.com/air-max".to_string(),
+            price: 120,
+            quantity: 3,
+            like: 0,
+            liked_by: Vec::new(),
+            created_at: 42,
+            updated_at: None,
+        };
+        let shoe = Shoe::from_bytes(Cow::Owned(Encode!(&stored).unwrap()));
+        assert_eq!(shoe.id, 7);
+        assert_eq!(shoe.category, None);
+        assert_eq!(_category_of(&shoe), "");
+    }
+
+    #[test]
+    fn category_of_normalizes_the_stored_category() {
+        let shoe = Shoe {
+            category: Some(" Running ".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(_category_of(&shoe), "running");
+    }
+
+    #[test]
+    fn related_shoes_rank_the_same_category_first() {
+        for (id, category, price, quantity) in [
+            (1, "running", 100, 1),
+            (2, "boots", 100, 1),
+            (3, "running", 160, 1),
+            (4, "Running", 110, 1),
+            (5, "running", 100, 0),
+        ] {
+            do_insert(&Shoe {
+                id,
+                category: Some(category.to_string()),
+                price,
+                quantity,
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = get_related_shoes(1, 10)
+            .ok()
+            .unwrap()
+            .iter()
+            .map(|shoe| shoe.id)
+            .collect();
+        // same category by closest price, then other categories; never itself or sold out
+        assert_eq!(ids, vec![4, 3, 2]);
+        assert_eq!(get_related_shoes(1, 1).ok().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn likes_given_follow_likes_and_unlikes() {
+        let fan = Principal::from_slice(&[1]);
+        let casual = Principal::from_slice(&[2]);
+        for id in [1, 2] {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+            assert!(_like_shoe_as(id, fan, 0).is_ok());
+        }
+        assert!(_like_shoe_as(1, casual, 0).is_ok());
+        assert_eq!(get_top_likers(10), vec![(fan, 2), (casual, 1)]);
+        assert_eq!(get_top_likers(1), vec![(fan, 2)]);
+
+        let shoe = _unlike_shoe_as(1, casual).ok().unwrap();
+        assert_eq!(shoe.like, 1);
+        assert_eq!(_shoe_likes(1), vec![(fan, 0)]);
+        assert_eq!(get_top_likers(10), vec![(fan, 2)]);
+        assert!(matches!(
+            _unlike_shoe_as(1, casual),
+            Err(Error::NotLiked { .. })
+        ));
+    }
+
+    #[test]
+    fn image_hashes_compare_trimmed_and_case_insensitively() {
+        do_insert(&Shoe {
+            id: 1,
+            image_hash: Some("ABCDEF".to_string()),
+            ..Default::default()
+        });
+        do_insert(&Shoe {
+            id: 2,
+            ..Default::default()
+        });
+        assert_eq!(
+            verify_image_hash(1, " abcdef ".to_string()).ok(),
+            Some(true)
+        );
+        assert_eq!(verify_image_hash(1, "abcde".to_string()).ok(), Some(false));
+        assert_eq!(verify_image_hash(2, "".to_string()).ok(), Some(false));
+        assert!(verify_image_hash(3, "abcdef".to_string()).is_err());
+    }
+
+    #[test]
+    fn shoes_by_owners_match_any_listed_owner() {
+        for (id, owner) in [(1, 1), (2, 2), (3, 3)] {
+            do_insert(&Shoe {
+                id,
+                owner: Principal::from_slice(&[owner]).to_string(),
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = get_shoes_by_owners(vec![
+            Principal::from_slice(&[1]),
+            Principal::from_slice(&[3]),
+        ])
+        .iter()
+        .map(|shoe| shoe.id)
+        .collect();
+        assert_eq!(ids, vec![1, 3]);
+        // owners past MAX_OWNERS_PER_QUERY are ignored
+        let mut owners = vec![Principal::anonymous(); MAX_OWNERS_PER_QUERY];
+        owners.push(Principal::from_slice(&[2]));
+        assert!(get_shoes_by_owners(owners).is_empty());
+    }
+
+    #[test]
+    fn purchases_take_stock_and_are_recorded_for_the_buyer() {
+        let buyer = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            price: 30,
+            quantity: 3,
+            ..Default::default()
+        });
+        let shoe = _purchase_shoe_as(1, 2, buyer, 100).ok().unwrap();
+        assert_eq!(shoe.quantity, 1);
+        assert!(matches!(
+            _purchase_shoe_as(1, 2, buyer, 200),
+            Err(Error::InsufficientStock { .. })
+        ));
+        assert!(matches!(
+            _purchase_shoe_as(1, 0, buyer, 200),
+            Err(Error::ValidationError { .. })
+        ));
+        let purchases = _purchases_of(buyer);
+        assert_eq!(purchases.len(), 1);
+        assert_eq!(purchases[0].amount, 2);
+        assert_eq!(purchases[0].total, 60);
+        assert_eq!(purchases[0].timestamp, 100);
+        assert!(_purchases_of(Principal::from_slice(&[2])).is_empty());
+    }
+
+    #[test]
+    fn low_stock_alerts_cover_the_owners_shoes_at_or_below_threshold() {
+        let owner = Principal::from_slice(&[1]).to_string();
+        for (id, owner, quantity, low_stock_threshold) in [
+            (1, owner.clone(), 2, Some(2)),
+            (2, owner.clone(), 3, Some(2)),
+            (3, owner.clone(), 0, None),
+            (4, Principal::from_slice(&[2]).to_string(), 1, Some(5)),
+        ] {
+            do_insert(&Shoe {
+                id,
+                owner,
+                quantity,
+                low_stock_threshold,
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = _low_stock_alerts(&owner)
+            .iter()
+            .map(|shoe| shoe.id)
+            .collect();
+        assert_eq!(ids, vec![1]);
+    }
+
+    #[test]
+    fn edits_are_recorded_in_the_shoe_history() {
+        let mut shoe = Shoe {
+            id: 1,
+            price: 100,
+            quantity: 3,
+            ..Default::default()
+        };
+        do_insert(&shoe);
+        shoe.price = 80;
+        shoe.like = 4;
+        shoe.updated_at = Some(Timestamp(5));
+        do_insert(&shoe);
+        let history = get_shoe_history(1);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].field, "price");
+        assert_eq!(history[0].old_value, "100");
+        assert_eq!(history[0].new_value, "80");
+        assert_eq!(history[0].timestamp, 5);
+
+        // stock changes from purchases stay out of the history
+        shoe.quantity = 1;
+        _insert_stock_change(&shoe);
+        assert_eq!(get_shoe_history(1).len(), 1);
+    }
+
+    #[test]
+    fn shoe_history_keeps_the_newest_entries() {
+        let mut shoe = Shoe {
+            id: 1,
+            updated_at: Some(Timestamp(1)),
+            ..Default::default()
+        };
+        do_insert(&shoe);
+        for price in 1..=(MAX_HISTORY_LEN as i16 + 5) {
+            shoe.price = price;
+            do_insert(&shoe);
+        }
+        let history = get_shoe_history(1);
+        assert_eq!(history.len(), MAX_HISTORY_LEN);
+        assert_eq!(
+            history.last().unwrap().new_value,
+            (MAX_HISTORY_LEN + 5).to_string()
+        );
+    }
+
+    #[test]
+    fn listings_mark_shoes_of_verified_sellers() {
+        let seller = Principal::from_slice(&[1]);
+        VERIFIED_SELLERS.with(|service| service.borrow_mut().insert(StorablePrincipal(seller), ()));
+        for (id, owner) in [(1, seller), (2, Principal::from_slice(&[2]))] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                ..Default::default()
+            });
+        }
+        let verified: Vec<(u64, bool)> = get_shoe_listings()
+            .iter()
+            .map(|listing| (listing.shoe.id, listing.verified))
+            .collect();
+        assert_eq!(verified, vec![(1, true), (2, false)]);
+    }
+
+    #[test]
+    fn discount_is_the_fraction_off_the_list_price() {
+        let shoe = Shoe {
+            price: 100,
+            sale_price: Some(80),
+            ..Default::default()
+        };
+        assert_eq!(_discount(&shoe), Some(0.2));
+    }
+
+    #[test]
+    fn discount_is_none_without_a_lower_sale_price() {
+        let no_sale = Shoe {
+            price: 100,
+            ..Default::default()
+        };
+        let not_lower = Shoe {
+            price: 100,
+            sale_price: Some(100),
+            ..Default::default()
+        };
+        let free = Shoe {
+            price: 0,
+            sale_price: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(_discount(&no_sale), None);
+        assert_eq!(_discount(&not_lower), None);
+        assert_eq!(_discount(&free), None);
+    }
+
+    #[test]
+    fn sale_prices_stay_below_the_price_and_above_the_floor() {
+        MIN_PRICE.with(|cell| cell.borrow_mut().set(50)).ok();
+        let shoe = Shoe {
+            price: 100,
+            ..Default::default()
+        };
+        assert!(_validate_sale_price(&shoe, 60).is_ok());
+        assert!(_validate_sale_price(&shoe, 100).is_err());
+        assert!(_validate_sale_price(&shoe, 0).is_err());
+        assert!(_validate_sale_price(&shoe, 40).is_err());
+    }
+
+    #[test]
+    fn purchases_of_a_shoe_on_sale_are_charged_the_sale_price() {
+        do_insert(&Shoe {
+            id: 1,
+            price: 100,
+            sale_price: Some(70),
+            quantity: 5,
+            ..Default::default()
+        });
+        let buyer = Principal::from_slice(&[1]);
+        assert!(_purchase_shoe_as(1, 2, buyer, 0).is_ok());
+        let purchase = &_purchases_of(buyer)[0];
+        assert_eq!(purchase.unit_price, 70);
+        assert_eq!(purchase.total, 140);
+    }
+
+    #[test]
+    fn best_deals_rank_the_biggest_discount_first() {
+        for (id, sale_price) in [(1, Some(90)), (2, None), (3, Some(50)), (4, Some(75))] {
+            do_insert(&Shoe {
+                id,
+                price: 100,
+                sale_price,
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = get_best_deals(2).iter().map(|shoe| shoe.id).collect();
+        assert_eq!(ids, vec![3, 4]);
+    }
+
+    #[test]
+    fn missing_shoes_are_not_found_before_ownership_is_checked() {
+        let owner = Principal::from_slice(&[1]);
+        let stranger = Principal::from_slice(&[2]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            ..Default::default()
+        });
+        assert!(_get_shoe_owned_by(1, owner).is_ok());
+        assert!(matches!(
+            _get_shoe_owned_by(1, stranger),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(matches!(
+            _get_shoe_owned_by(2, stranger),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn convert_size_reads_the_size_chart() {
+        assert_eq!(_convert_size(10.0, SizeSystem::US, SizeSystem::EU), 44.0);
+        assert_eq!(_convert_size(44.0, SizeSystem::EU, SizeSystem::US), 10.0);
+        assert_eq!(_convert_size(10.0, SizeSystem::US, SizeSystem::UK), 9.5);
+    }
+
+    #[test]
+    fn convert_size_interpolates_and_extrapolates() {
+        assert_eq!(_convert_size(13.5, SizeSystem::US, SizeSystem::EU), 48.0);
+        assert_eq!(_convert_size(16.0, SizeSystem::US, SizeSystem::EU), 50.5);
+    }
+
+    #[test]
+    fn convert_size_keeps_sizes_in_the_same_system() {
+        assert_eq!(_convert_size(10.3, SizeSystem::US, SizeSystem::US), 10.3);
+    }
+
+    #[test]
+    fn transfer_moves_only_the_owners_shoes() {
+        let owner = Principal::from_slice(&[1]).to_string();
+        let new_owner = Principal::from_slice(&[2]);
+        let other = Principal::from_slice(&[3]).to_string();
+        for (id, owner) in [(1, owner.clone()), (2, other.clone()), (3, owner.clone())] {
+            do_insert(&Shoe {
+                id,
+                owner,
+                ..Default::default()
+            });
+        }
+        assert_eq!(_transfer_shoes(&owner, new_owner, 9).len(), 2);
+        for id in [1, 3] {
+            let shoe = _get_shoe(&id).unwrap();
+            assert_eq!(shoe.owner, new_owner.to_string());
+            assert_eq!(shoe.updated_at, Some(Timestamp(9)));
+        }
+        assert_eq!(_get_shoe(&2).unwrap().owner, other);
+        assert!(_transfer_shoes(&owner, new_owner, 10).is_empty());
+    }
+
+    #[test]
+    fn search_and_sort_filters_sorts_and_pages() {
+        for (id, name, category, price, quantity) in [
+            (1, "Air Max", "running", 120, 2),
+            (2, "Air Force", "lifestyle", 90, 1),
+            (3, "Air Zoom", "Running", 150, 0),
+            (4, "Pegasus AIR", "running", 100, 5),
+            (5, "Chelsea", "boots", 80, 3),
+        ] {
+            do_insert(&Shoe {
+                id,
+                name: name.to_string(),
+                category: Some(category.to_string()),
+                price,
+                quantity,
+                ..Default::default()
+            });
+        }
+        let filter = ShoeFilter {
+            name: Some(" air ".to_string()),
+            category: Some("RUNNING".to_string()),
+            ..Default::default()
+        };
+        let page = search_and_sort(filter, SortBy::Price, true, 0, 2);
+        let ids: Vec<u64> = page.shoes.iter().map(|shoe| shoe.id).collect();
+        assert_eq!(ids, vec![3, 1]);
+        assert_eq!(page.total, 3);
+
+        let filter = ShoeFilter {
+            min_price: Some(90),
+            max_price: Some(120),
+            in_stock: Some(true),
+            ..Default::default()
+        };
+        let page = search_and_sort(filter, SortBy::Name, false, 1, 10);
+        let ids: Vec<u64> = page.shoes.iter().map(|shoe| shoe.id).collect();
+        assert_eq!(ids, vec![1, 4]);
+        assert_eq!(page.total, 3);
+    }
+
+    #[test]
+    fn search_and_sort_caps_the_page_size() {
+        let page = search_and_sort(ShoeFilter::default(), SortBy::Likes, false, 0, u64::MAX);
+        assert_eq!(page.limit, DEFAULT_MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn each_principal_reports_a_shoe_once() {
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        assert_eq!(
+            _report_shoe_as(1, Principal::from_slice(&[1]), 0).ok(),
+            Some(1)
+        );
+        assert!(matches!(
+            _report_shoe_as(1, Principal::from_slice(&[1]), 0),
+            Err(Error::AlreadyReported { .. })
+        ));
+        assert_eq!(
+            _report_shoe_as(1, Principal::from_slice(&[2]), 0).ok(),
+            Some(2)
+        );
+        assert!(matches!(
+            _report_shoe_as(2, Principal::from_slice(&[1]), 0),
+            Err(Error::NotFound { .. })
+        ));
+        _clear_reports(1);
+        assert_eq!(_report_count(1), 0);
+    }
+
+    #[test]
+    fn flagged_shoes_are_ordered_by_report_count() {
+        for id in 1..=3 {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+        }
+        for reporter in 1..=3 {
+            _report_shoe_as(2, Principal::from_slice(&[reporter]), 0).ok();
+        }
+        _report_shoe_as(1, Principal::from_slice(&[1]), 0).ok();
+        // reports of the neighbouring shoes don't leak into each other's count
+        assert_eq!(_report_count(3), 0);
+        let flagged: Vec<(u64, u64)> = _flagged_shoes(0)
+            .iter()
+            .map(|(shoe, count)| (shoe.id, *count))
+            .collect();
+        assert_eq!(flagged, vec![(2, 3), (1, 1)]);
+        assert_eq!(_flagged_shoes(2).len(), 1);
+    }
+
+    #[test]
+    fn image_urls_need_an_http_scheme_and_a_host() {
+        assert!(is_valid_image_url("https://cdn.example.com/shoe.png"));
+        assert!(is_valid_image_url("http://example.com/a"));
+        assert!(!is_valid_image_url("ftp://example.com/shoe.png"));
+        assert!(!is_valid_image_url("https:///shoe.png"));
+        assert!(!is_valid_image_url("https://"));
+        assert!(!is_valid_image_url("https://example.com/my shoe.png"));
+        assert!(!is_valid_image_url("example.com/shoe.png"));
+    }
+
+    #[test]
+    fn image_urls_are_capped_in_length() {
+        let url = format!("https://example.com/{}", "a".repeat(MAX_IMAGE_URL_LEN));
+        assert!(!is_valid_image_url(&url));
+    }
+
+    #[test]
+    fn image_lists_are_capped_in_count() {
+        let images = vec!["https://example.com/a.png".to_string(); MAX_IMAGES + 1];
+        assert!(validate_images(&images[..MAX_IMAGES]).is_ok());
+        assert!(validate_images(&images).is_err());
+    }
+
+    #[test]
+    fn the_largest_image_list_fits_its_bound() {
+        let url = format!(
+            "https://{}",
+            "a".repeat(MAX_IMAGE_URL_LEN - "https://".len())
+        );
+        let images = ImageList(vec![url; MAX_IMAGES]);
+        assert!(images.to_bytes().len() <= ImageList::MAX_SIZE as usize);
+    }
+
+    #[test]
+    fn setting_no_images_drops_the_entry() {
+        _set_images(1, vec!["https://example.com/a.png".to_string()]);
+        assert_eq!(_get_images(1).len(), 1);
+        _set_images(1, Vec::new());
+        assert!(SHOE_IMAGES.with(|service| service.borrow().get(&1).is_none()));
+    }
+
+    #[test]
+    fn get_shoe_by_id_opt_is_none_for_a_missing_shoe() {
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        assert_eq!(get_shoe_by_id_opt(1).map(|shoe| shoe.id), Some(1));
+        assert!(get_shoe_by_id_opt(2).is_none());
+    }
+
+    #[test]
+    fn trending_is_served_from_the_refreshed_cache() {
+        for (id, like, created_at) in [(1, 5, 0), (2, 8, 0), (3, 9, 996_000_000_000)] {
+            do_insert(&Shoe {
+                id,
+                like,
+                created_at: Timestamp(created_at),
+                ..Default::default()
+            });
+        }
+        let now = 1_000_000_000_000;
+        let ids: Vec<u64> = _compute_trending(2, now)
+            .iter()
+            .map(|shoe| shoe.id)
+            .collect();
+        assert_eq!(ids, vec![3, 2]);
+
+        _refresh_trending_cache(now);
+        // a later like doesn't reorder the cached ranking until the next refresh
+        let mut shoe = _get_shoe(&1).unwrap();
+        shoe.like = 1_000;
+        do_insert(&shoe);
+        let ids: Vec<u64> = get_trending_shoes(3).iter().map(|shoe| shoe.id).collect();
+        assert_eq!(ids, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn comments_are_trimmed_and_bounded() {
+        let author = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        let comment = _add_comment_as(1, "  Runs small?  ".to_string(), author, 7)
+            .ok()
+            .unwrap();
+        assert_eq!(comment.text, "Runs small?");
+        assert_eq!(comment.created_at, 7);
+        assert!(_add_comment_as(1, "   ".to_string(), author, 7).is_err());
+        // the limit is in bytes, so multi-byte text hits it sooner
+        let text = "é".repeat(MAX_COMMENT_LEN / 2 + 1);
+        assert!(_add_comment_as(1, text, author, 7).is_err());
+        assert!(matches!(
+            _add_comment_as(2, "Hello".to_string(), author, 7),
+            Err(Error::NotFound { .. })
+        ));
+        assert_eq!(get_comments(1).len(), 1);
+    }
+
+    #[test]
+    fn the_longest_comment_fits_its_bound() {
+        let comment = Comment {
+            id: u64::MAX,
+            shoe_id: u64::MAX,
+            author: Principal::from_slice(&[0; 29]),
+            text: "a".repeat(MAX_COMMENT_LEN),
+            created_at: u64::MAX,
+        };
+        assert!(comment.to_bytes().len() <= Comment::MAX_SIZE as usize);
+    }
+
+    #[test]
+    fn only_the_author_or_an_admin_deletes_a_comment() {
+        let author = Principal::from_slice(&[1]);
+        let stranger = Principal::from_slice(&[2]);
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            _add_comment_as(1, "Hello".to_string(), author, 0).ok();
+        }
+        assert!(matches!(
+            _delete_comment_as(0, stranger, false),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(_delete_comment_as(0, author, false).is_ok());
+        assert!(_delete_comment_as(1, stranger, true).is_ok());
+        assert!(matches!(
+            _delete_comment_as(1, author, false),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn stale_shoes_were_never_updated_since_the_cutoff() {
+        let second = 1_000_000_000;
+        for (id, created_at, updated_at) in [
+            (1, 10 * second, None),
+            (2, 10 * second, Some(50 * second)),
+            (3, 95 * second, None),
+        ] {
+            do_insert(&Shoe {
+                id,
+                created_at: Timestamp(created_at),
+                updated_at: updated_at.map(Timestamp),
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = _stale_shoes(30, 100 * second)
+            .iter()
+            .map(|shoe| shoe.id)
+            .collect();
+        assert_eq!(ids, vec![1]);
+        assert!(_stale_shoes(u64::MAX, 100 * second).is_empty());
+    }
+
+    #[test]
+    fn sizes_are_required_outside_sizeless_categories() {
+        assert!(validate_size_for_category("running", "US 10").is_ok());
+        assert!(validate_size_for_category(" Accessories ", "").is_ok());
+        assert!(validate_size_for_category("running", "  ").is_err());
+        assert!(validate_size_for_category("", "").is_err());
+    }
+
+    #[test]
+    fn shoe_json_round_trips() {
+        do_insert(&Shoe {
+            id: 1,
+            owner: Principal::from_slice(&[1]).to_string(),
+            name: "Air Max".to_string(),
+            category: Some("running".to_string()),
+            shoe_size: parse_shoe_size("US 10"),
+            price: 120,
+            updated_at: Some(Timestamp(5)),
+            ..Default::default()
+        });
+        let json = get_shoe_json(1).ok().unwrap();
+        let shoe: Shoe = serde_json::from_str(&json).unwrap();
+        assert_eq!(shoe.name, "Air Max");
+        assert_eq!(shoe.category.as_deref(), Some("running"));
+        assert_eq!(shoe.shoe_size, parse_shoe_size("US 10"));
+        assert_eq!(shoe.updated_at, Some(Timestamp(5)));
+        assert!(get_shoe_json(2).is_err());
+    }
+
+    #[test]
+    fn quantity_is_capped_by_max_quantity() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            quantity: 8,
+            ..Default::default()
+        });
+        MAX_QUANTITY.with(|c| c.borrow_mut().set(10)).ok();
+        assert!(validate_max_quantity(10).is_ok());
+        assert!(matches!(
+            validate_max_quantity(11),
+            Err(Error::ValidationError { .. })
+        ));
+        assert_eq!(_restocked_shoe(1, 2, owner).ok().unwrap().quantity, 10);
+        assert!(matches!(
+            _restocked_shoe(1, 3, owner),
+            Err(Error::ValidationError { .. })
+        ));
+        let mut shoe = _get_shoe(&1).unwrap();
+        let update = ShoeUpdate {
+            quantity: Some(11),
+            ..Default::default()
+        };
+        assert!(_apply_update(&mut shoe, update).is_err());
+    }
+
+    #[test]
+    fn likers_are_paged_in_like_order() {
+        // likers in reverse principal order, so the like times decide the order
+        let likers: Vec<Principal> = (1..=5).rev().map(|n| Principal::from_slice(&[n])).collect();
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        for (liked_at, liker) in likers.iter().enumerate() {
+            assert!(_like_shoe_as(1, *liker, liked_at as u64).is_ok());
+        }
+        assert_eq!(get_likers(1, 1, 2).ok().unwrap(), likers[1..3].to_vec());
+        assert_eq!(get_likers(1, 4, 10).ok().unwrap(), likers[4..].to_vec());
+        assert!(get_likers(1, 9, 10).ok().unwrap().is_empty());
+        assert!(matches!(get_likers(2, 0, 1), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn identical_shoe_matches_owner_and_listing_fields() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            name: "Air Max".to_string(),
+            size: "US 10".to_string(),
+            price: 120,
+            category: Some("running".to_string()),
+            ..Default::default()
+        });
+        let payload = ShoePayload {
+            name: " Air Max ".to_string(),
+            size: "US 10".to_string(),
+            price: 120,
+            category: Some(" Running".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(_find_identical_shoe(&payload, owner).map(|s| s.id), Some(1));
+        assert!(_find_identical_shoe(&payload, Principal::from_slice(&[2])).is_none());
+        let other_price = ShoePayload {
+            price: 130,
+            ..payload
+        };
+        assert!(_find_identical_shoe(&other_price, owner).is_none());
+    }
+
+    #[test]
+    fn size_range_matches_across_sizing_systems() {
+        for (id, size) in [(1, "US 10"), (2, "EU 44"), (3, "US 13"), (4, "XL")] {
+            do_insert(&Shoe {
+                id,
+                size: size.to_string(),
+                shoe_size: parse_shoe_size(size),
+                ..Default::default()
+            });
+        }
+        let ids: Vec<u64> = search_by_size_range(9.5, 10.5, SizeSystem::US)
+            .iter()
+            .map(|shoe| shoe.id)
+            .collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(search_by_size_range(30.0, 31.0, SizeSystem::EU).is_empty());
+    }
+
+    #[test]
+    fn shoe_return_policy_overrides_the_sellers() {
+        let seller = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: seller.to_string(),
+            ..Default::default()
+        });
+        assert_eq!(get_shoe_return_policy(1).ok().unwrap(), "");
+        SELLER_PROFILES.with(|service| {
+            service.borrow_mut().insert(
+                StorablePrincipal(seller),
+                SellerProfile {
+                    display_name: "Shoe Shop".to_string(),
+                    return_policy: "30 days".to_string(),
+                },
+            )
+        });
+        assert_eq!(get_shoe_return_policy(1).ok().unwrap(), "30 days");
+        assert!(_set_return_policy(1, Some("final sale".to_string())).is_ok());
+        assert_eq!(get_shoe_return_policy(1).ok().unwrap(), "final sale");
+        assert!(_set_return_policy(1, Some("x".repeat(MAX_RETURN_POLICY_LEN + 1))).is_err());
+        assert!(_set_return_policy(1, None).is_ok());
+        assert_eq!(get_shoe_return_policy(1).ok().unwrap(), "30 days");
+        assert!(matches!(
+            get_shoe_return_policy(2),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn the_longest_return_policies_fit_their_bounds() {
+        let policy = ReturnPolicy("é".repeat(MAX_RETURN_POLICY_LEN / 2));
+        assert!(policy.to_bytes().len() <= ReturnPolicy::MAX_SIZE as usize);
+        let profile = SellerProfile {
+            display_name: "é".repeat(MAX_DISPLAY_NAME_LEN / 2),
+            return_policy: "é".repeat(MAX_RETURN_POLICY_LEN / 2),
+        };
+        assert!(profile.to_bytes().len() <= SellerProfile::MAX_SIZE as usize);
+    }
+
+    #[test]
+    fn listing_count_counts_every_stored_shoe() {
+        assert_eq!(listing_count(), 0);
+        for id in 1..=3 {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+        }
+        assert_eq!(listing_count(), 3);
+    }
+
+    #[test]
+    fn sku_index_follows_inserts_updates_and_removals() {
+        let mut shoe = Shoe {
+            id: 1,
+            sku: Some("AM-90".to_string()),
+            ..Default::default()
+        };
+        do_insert(&shoe);
+        assert_eq!(
+            get_shoe_by_sku(" am-90 ".to_string()).ok().map(|s| s.id),
+            Some(1)
+        );
+        assert!(matches!(
+            validate_sku("am-90", None),
+            Err(Error::DuplicateSku { .. })
+        ));
+        assert!(validate_sku("am-90", Some(1)).is_ok());
+        shoe.sku = Some("AM-95".to_string());
+        shoe.updated_at = Some(Timestamp(1));
+        do_insert(&shoe);
+        assert!(get_shoe_by_sku("AM-90".to_string()).is_err());
+        assert_eq!(
+            get_shoe_by_sku("AM-95".to_string()).ok().map(|s| s.id),
+            Some(1)
+        );
+        _update_indexes(Some(&shoe), None);
+        assert!(get_shoe_by_sku("AM-95".to_string()).is_err());
+    }
+
+    #[test]
+    fn skus_are_optional_and_bounded() {
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        assert!(validate_sku("", None).is_ok());
+        assert!(validate_sku(&"X".repeat(StorableString::MAX_SIZE as usize), None).is_ok());
+        assert!(validate_sku(&"X".repeat(StorableString::MAX_SIZE as usize + 1), None).is_err());
+    }
+
+    #[test]
+    fn recent_price_drops_report_the_latest_drop_after_since() {
+        let mut shoe = Shoe {
+            id: 1,
+            price: 100,
+            updated_at: Some(Timestamp(5)),
+            ..Default::default()
+        };
+        do_insert(&shoe);
+        for (price, at) in [(80, 10), (90, 20)] {
+            shoe.price = price;
+            shoe.updated_at = Some(Timestamp(at));
+            do_insert(&shoe);
+        }
+        let drops: Vec<(u64, i16, i16)> = get_recent_price_drops(9)
+            .into_iter()
+            .map(|(shoe, old_price, new_price)| (shoe.id, old_price, new_price))
+            .collect();
+        assert_eq!(drops, vec![(1, 100, 80)]);
+        assert!(get_recent_price_drops(10).is_empty());
+    }
+
+    fn shoes_with_ids(ids: &[u64]) -> Vec<Shoe> {
+        ids.iter()
+            .map(|&id| Shoe {
+                id,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn ids_of(shoes: &[Shoe]) -> Vec<u64> {
+        shoes.iter().map(|shoe| shoe.id).collect()
+    }
+
+    #[test]
+    fn rotating_window_moves_one_shoe_per_interval() {
+        let second = 1_000_000_000;
+        let pool = shoes_with_ids(&[0, 1, 2, 3]);
+        assert_eq!(
+            ids_of(&_rotating_window(pool.clone(), 2, 0, 60)),
+            vec![0, 1]
+        );
+        assert_eq!(
+            ids_of(&_rotating_window(pool.clone(), 2, 59 * second, 60)),
+            vec![0, 1]
+        );
+        assert_eq!(
+            ids_of(&_rotating_window(pool.clone(), 2, 60 * second, 60)),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids_of(&_rotating_window(pool, 2, 180 * second, 60)),
+            vec![3, 0]
+        );
+    }
+
+    #[test]
+    fn rotating_window_is_capped_at_the_pool() {
+        let pool = shoes_with_ids(&[0, 1, 2]);
+        assert_eq!(ids_of(&_rotating_window(pool, 5, 0, 60)), vec![0, 1, 2]);
+        assert!(_rotating_window(Vec::new(), 3, 0, 60).is_empty());
+    }
+
+    #[test]
+    fn only_stored_shoes_can_be_featured() {
+        for id in [2, 1] {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+        }
+        assert!(matches!(
+            _set_featured(3, true),
+            Err(Error::NotFound { .. })
+        ));
+        assert!(_set_featured(2, true).is_ok());
+        assert!(_set_featured(1, true).is_ok());
+        assert_eq!(ids_of(&get_featured_shoes()), vec![1, 2]);
+        assert!(_set_featured(1, false).is_ok());
+        assert_eq!(ids_of(&get_featured_shoes()), vec![2]);
+    }
+
+    #[test]
+    fn soft_deleted_shoes_are_not_listed() {
+        let listed = Shoe::default();
+        let deleted = Shoe {
+            deleted_at: Some(1),
+            ..Default::default()
+        };
+        assert!(_is_listed(&listed));
+        assert!(!_is_listed(&deleted));
+        assert!(_ensure_listed(&listed).is_ok());
+        assert!(matches!(
+            _ensure_listed(&deleted),
+            Err(Error::ShoeDeleted { .. })
+        ));
+    }
+
+    #[test]
+    fn shoe_status_covers_every_bucket() {
+        let active = Shoe {
+            quantity: 1,
+            ..Default::default()
+        };
+        let out_of_stock = Shoe {
+            quantity: 0,
+            ..Default::default()
+        };
+        let deleted = Shoe {
+            quantity: 1,
+            deleted_at: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(_shoe_status(&active), ShoeStatus::Active);
+        assert_eq!(_shoe_status(&out_of_stock), ShoeStatus::OutOfStock);
+        assert_eq!(_shoe_status(&deleted), ShoeStatus::Deleted);
+    }
+
+    #[test]
+    fn soft_deleted_shoes_are_hidden_from_listings_and_buyers() {
+        let owner = Principal::from_slice(&[1]);
+        let buyer = Principal::from_slice(&[2]);
+        for id in 1..=2 {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                name: "Air Max".to_string(),
+                quantity: 5,
+                deleted_at: (id == 1).then_some(1),
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&get_shoes()), vec![2]);
+        assert_eq!(ids_of(&search_by_name("Air Max".to_string())), vec![2]);
+        assert_eq!(ids_of(&get_shoes_by_owners(vec![owner])), vec![2]);
+        assert!(matches!(
+            _like_shoe_as(1, buyer, 0),
+            Err(Error::ShoeDeleted { .. })
+        ));
+        assert!(matches!(
+            _purchase_shoe_as(1, 1, buyer, 0),
+            Err(Error::ShoeDeleted { .. })
+        ));
+        assert!(matches!(
+            _report_shoe_as(1, buyer, 0),
+            Err(Error::ShoeDeleted { .. })
+        ));
+        assert!(matches!(
+            _add_comment_as(1, "size?".to_string(), buyer, 0),
+            Err(Error::ShoeDeleted { .. })
+        ));
+        let payload = ShoePayload {
+            name: "Air Max".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(_find_identical_shoe(&payload, owner).map(|s| s.id), Some(2));
+    }
+
+    #[test]
+    fn purchases_credit_the_seller() {
+        let seller = Principal::from_slice(&[1]);
+        let buyer = Principal::from_slice(&[2]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: seller.to_string(),
+            price: 100,
+            sale_price: Some(80),
+            quantity: 5,
+            ..Default::default()
+        });
+        assert_eq!(_balance_of(seller), 0);
+        assert!(_purchase_shoe_as(1, 2, buyer, 0).is_ok());
+        assert!(_purchase_shoe_as(1, 1, buyer, 0).is_ok());
+        assert_eq!(_balance_of(seller), 240);
+        assert_eq!(_balance_of(buyer), 0);
+        assert!(_purchase_shoe_as(1, 3, buyer, 0).is_err());
+        assert_eq!(_balance_of(seller), 240);
+    }
+
+    #[test]
+    fn shoe_fields_are_projected_as_text() {
+        do_insert(&Shoe {
+            id: 1,
+            name: "Air Max".to_string(),
+            category: Some("running".to_string()),
+            price: 120,
+            ..Default::default()
+        });
+        let field = |field| get_shoe_field(1, field).ok().unwrap();
+        assert_eq!(field(ShoeField::Name), "Air Max");
+        assert_eq!(field(ShoeField::Category), "running");
+        assert_eq!(field(ShoeField::Price), "120");
+        assert_eq!(field(ShoeField::Sku), "");
+        assert_eq!(field(ShoeField::SalePrice), "");
+        assert!(matches!(
+            get_shoe_field(2, ShoeField::Name),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn reservations_hold_stock_until_released() {
+        let buyer = Principal::from_slice(&[2]);
+        do_insert(&Shoe {
+            id: 1,
+            quantity: 3,
+            ..Default::default()
+        });
+        let reservation = _reserve_shoe_as(1, 2, buyer, 0).ok().unwrap();
+        assert_eq!(reservation.expires_at, RESERVATION_TTL);
+        assert_eq!(_get_shoe(&1).unwrap().quantity, 1);
+        assert!(matches!(
+            _reserve_shoe_as(1, 2, buyer, 0),
+            Err(Error::InsufficientStock { .. })
+        ));
+        assert!(matches!(
+            _release_reservation_as(reservation.id, Principal::from_slice(&[3])),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(_release_reservation_as(reservation.id, buyer).is_ok());
+        assert_eq!(_get_shoe(&1).unwrap().quantity, 3);
+        assert!(_reservations_of(buyer).is_empty());
+    }
+
+    #[test]
+    fn expired_reservations_go_back_to_stock() {
+        let buyer = Principal::from_slice(&[2]);
+        do_insert(&Shoe {
+            id: 1,
+            quantity: 3,
+            ..Default::default()
+        });
+        assert!(_reserve_shoe_as(1, 1, buyer, 0).is_ok());
+        assert!(_reserve_shoe_as(1, 1, buyer, 10).is_ok());
+        assert_eq!(_release_expired_reservations(RESERVATION_TTL), 1);
+        assert_eq!(_get_shoe(&1).unwrap().quantity, 2);
+        assert_eq!(_reservations_of(buyer).len(), 1);
+    }
+
+    #[test]
+    fn purchases_consume_the_buyers_reservations() {
+        let buyer = Principal::from_slice(&[2]);
+        let other = Principal::from_slice(&[3]);
+        do_insert(&Shoe {
+            id: 1,
+            quantity: 3,
+            ..Default::default()
+        });
+        assert!(_reserve_shoe_as(1, 3, buyer, 0).is_ok());
+        assert!(matches!(
+            _purchase_shoe_as(1, 1, other, 0),
+            Err(Error::InsufficientStock { .. })
+        ));
+        // the last units can be bought by the buyer who reserved them
+        assert_eq!(_purchase_shoe_as(1, 2, buyer, 1).ok().unwrap().quantity, 1);
+        assert!(_reservations_of(buyer).is_empty());
+        assert_eq!(_purchases_of(buyer).len(), 1);
+        // an expired reservation no longer counts towards the purchase
+        assert!(_reserve_shoe_as(1, 1, buyer, 0).is_ok());
+        assert!(matches!(
+            _purchase_shoe_as(1, 1, buyer, RESERVATION_TTL),
+            Err(Error::InsufficientStock { .. })
+        ));
+    }
+
+    #[test]
+    fn sitemap_lists_the_last_change_of_listed_shoes() {
+        for (id, updated_at, deleted_at) in
+            [(1, None, None), (2, Some(20), None), (3, None, Some(30))]
+        {
+            do_insert(&Shoe {
+                id,
+                created_at: Timestamp(10),
+                updated_at: updated_at.map(Timestamp),
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        assert_eq!(sitemap_entries(), vec![(1, 10), (2, 20)]);
+    }
+
+    #[test]
+    fn get_shoes_follows_the_default_sort() {
+        for (id, price, created_at) in [(1, 100, 30), (2, 300, 10), (3, 200, 20)] {
+            do_insert(&Shoe {
+                id,
+                price,
+                created_at: Timestamp(created_at),
+                ..Default::default()
+            });
+        }
+        assert_eq!(get_default_sort(), SortBy::CreatedAt);
+        assert_eq!(ids_of(&get_shoes()), vec![2, 3, 1]);
+        DEFAULT_SORT
+            .with(|cell| cell.borrow_mut().set(SortBy::Price))
+            .ok();
+        assert_eq!(ids_of(&get_shoes()), vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn reviews_are_one_per_reviewer_and_averaged() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            ..Default::default()
+        });
+        let reviewers = [Principal::from_slice(&[2]), Principal::from_slice(&[3])];
+        assert!(_review_shoe_as(1, 2, "tight".to_string(), reviewers[0], 0).is_ok());
+        assert!(_review_shoe_as(1, 5, " great ".to_string(), reviewers[1], 0).is_ok());
+        assert!(_review_shoe_as(1, 3, String::new(), reviewers[0], 1).is_ok());
+        assert!(matches!(
+            _review_shoe_as(1, 5, String::new(), owner, 0),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(_review_shoe_as(1, 6, String::new(), reviewers[1], 0).is_err());
+        let reviews = get_reviews(1);
+        assert_eq!(reviews.len(), 2);
+        assert!(reviews.iter().any(|review| review.text == "great"));
+        let rated = get_shoes_with_ratings();
+        assert_eq!(rated[0].review_count, 2);
+        assert_eq!(rated[0].average_rating, 4.0);
+    }
+
+    #[test]
+    fn review_text_is_limited_in_bytes() {
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        let reviewer = Principal::from_slice(&[2]);
+        let longest = "é".repeat(MAX_COMMENT_LEN / 2);
+        assert!(_review_shoe_as(1, 4, format!("{}é", longest), reviewer, 0).is_err());
+        let review = _review_shoe_as(1, 4, longest, reviewer, 0).ok().unwrap();
+        assert!(review.to_bytes().len() <= Review::MAX_SIZE as usize);
+    }
+
+    #[test]
+    fn name_tokens_are_unique_lowercase_words() {
+        assert_eq!(
+            _name_tokens("Air Max-90 air"),
+            vec!["air".to_string(), "max".to_string(), "90".to_string()]
+        );
+        assert!(_name_tokens(" -- ").is_empty());
+    }
+
+    #[test]
+    fn name_tokens_fit_an_index_key() {
+        let max = StorableString::MAX_SIZE as usize;
+        let tokens = _name_tokens(&"a".repeat(max + 10));
+        assert_eq!(tokens, vec!["a".repeat(max)]);
+    }
+
+    fn name_index_key(token: &str) -> Option<Vec<u64>> {
+        NAME_INDEX.with(|service| {
+            service
+                .borrow()
+                .get(&StorableString(token.to_string()))
+                .map(|ids| ids.0)
+        })
+    }
+
+    #[test]
+    fn name_index_follows_renames_and_removals() {
+        for (id, name) in [(1, "Air Max"), (2, "Air Force")] {
+            do_insert(&Shoe {
+                id,
+                name: name.to_string(),
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            ids_of(&search_by_name_contains("AI".to_string())),
+            vec![1, 2]
+        );
+        assert_eq!(
+            ids_of(&search_by_name_contains("air ma".to_string())),
+            vec![1]
+        );
+        let mut shoe = _get_shoe(&1).unwrap();
+        shoe.name = "Dunk Low".to_string();
+        shoe.updated_at = Some(Timestamp(1));
+        do_insert(&shoe);
+        assert!(search_by_name_contains("max".to_string()).is_empty());
+        assert_eq!(
+            ids_of(&search_by_name_contains("dunk".to_string())),
+            vec![1]
+        );
+        assert_eq!(name_index_key("air"), Some(vec![2]));
+        let removed = SHOE_STORAGE.with(|service| service.borrow_mut().remove(&2));
+        _update_indexes(removed.as_ref(), None);
+        assert_eq!(name_index_key("air"), None);
+        assert_eq!(name_index_key("force"), None);
+    }
+
+    #[test]
+    fn overflowed_name_tokens_fall_back_to_a_scan_until_reindexed() {
+        let full = IdList((1_000..1_000 + MAX_IDS_PER_INDEX_KEY as u64).collect());
+        NAME_INDEX.with(|service| {
+            service
+                .borrow_mut()
+                .insert(StorableString("air".to_string()), full)
+        });
+        do_insert(&Shoe {
+            id: 1,
+            name: "Air Max".to_string(),
+            ..Default::default()
+        });
+        assert!(!name_index_key("air").unwrap().contains(&1));
+        assert_eq!(ids_of(&search_by_name_contains("air".to_string())), vec![1]);
+        // the bucket has room again, but still misses the shoe that didn't fit
+        _move_in_index(
+            &NAME_INDEX,
+            &NAME_INDEX_OVERFLOW,
+            1_000,
+            vec!["air".to_string()],
+            Vec::new(),
+        );
+        assert_eq!(ids_of(&search_by_name_contains("air".to_string())), vec![1]);
+        _reindex_shoes();
+        assert_eq!(name_index_key("air"), Some(vec![1]));
+        assert!(NAME_INDEX_OVERFLOW.with(|service| service.borrow().is_empty()));
+        assert_eq!(ids_of(&search_by_name_contains("air".to_string())), vec![1]);
+    }
+
+    #[test]
+    fn size_keys_ignore_case_and_whitespace() {
+        assert_eq!(_size_key(" us 10.5 "), "US10.5");
+        assert_eq!(_size_index_key("eu 44"), Some("EU44".to_string()));
+        assert_eq!(_size_index_key("  "), None);
+        let too_long = "9".repeat(StorableString::MAX_SIZE as usize + 1);
+        assert_eq!(_size_index_key(&too_long), None);
+    }
+
+    #[test]
+    fn size_index_follows_size_changes_and_removals() {
+        for (id, size) in [(1, "US 10"), (2, "us10")] {
+            do_insert(&Shoe {
+                id,
+                size: size.to_string(),
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&search_by_size("US10".to_string())), vec![1, 2]);
+        let mut shoe = _get_shoe(&1).unwrap();
+        shoe.size = "US 11".to_string();
+        shoe.updated_at = Some(Timestamp(1));
+        do_insert(&shoe);
+        assert_eq!(ids_of(&search_by_size("us 10".to_string())), vec![2]);
+        assert_eq!(ids_of(&search_by_size("us 11".to_string())), vec![1]);
+        let removed = SHOE_STORAGE.with(|service| service.borrow_mut().remove(&2));
+        _update_indexes(removed.as_ref(), None);
+        assert!(SIZE_INDEX.with(|service| service
+            .borrow()
+            .get(&StorableString("US10".to_string()))
+            .is_none()));
+        assert!(search_by_size("us 10".to_string()).is_empty());
+    }
+
+    #[test]
+    fn overflowed_sizes_fall_back_to_a_scan_until_reindexed() {
+        let key = StorableString("US10".to_string());
+        let full = IdList((1_000..1_000 + MAX_IDS_PER_INDEX_KEY as u64).collect());
+        SIZE_INDEX.with(|service| service.borrow_mut().insert(key.clone(), full));
+        do_insert(&Shoe {
+            id: 1,
+            size: "US 10".to_string(),
+            ..Default::default()
+        });
+        _move_in_index(
+            &SIZE_INDEX,
+            &SIZE_INDEX_OVERFLOW,
+            1_000,
+            vec!["US10".to_string()],
+            Vec::new(),
+        );
+        assert_eq!(ids_of(&search_by_size("US 10".to_string())), vec![1]);
+        _reindex_shoes();
+        assert!(!SIZE_INDEX_OVERFLOW.with(|service| service.borrow().contains_key(&key)));
+        assert_eq!(
+            SIZE_INDEX
+                .with(|service| service.borrow().get(&key))
+                .map(|ids| ids.0),
+            Some(vec![1])
+        );
+        assert_eq!(ids_of(&search_by_size("US 10".to_string())), vec![1]);
+    }
+
+    #[test]
+    fn shoes_with_blank_sizes_are_missing_a_size() {
+        for (id, size) in [(1, "US 10"), (2, " "), (3, "")] {
+            do_insert(&Shoe {
+                id,
+                size: size.to_string(),
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&get_shoes_missing_size()), vec![2, 3]);
+    }
+
+    #[test]
+    fn wishlist_skips_duplicates_and_stops_at_the_cap() {
+        let owner = Principal::from_slice(&[4]);
+        for id in 0..=MAX_WISHLIST_LEN as u64 {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+        }
+        assert_eq!(_add_to_wishlist_as(0, owner).ok(), Some(1));
+        assert_eq!(_add_to_wishlist_as(0, owner).ok(), Some(1));
+        for id in 1..MAX_WISHLIST_LEN as u64 {
+            assert!(_add_to_wishlist_as(id, owner).is_ok());
+        }
+        assert!(matches!(
+            _add_to_wishlist_as(MAX_WISHLIST_LEN as u64, owner),
+            Err(Error::WishlistFull { .. })
+        ));
+        assert!(matches!(
+            _add_to_wishlist_as(MAX_WISHLIST_LEN as u64 + 1, owner),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn deleted_shoes_cant_be_wishlisted_and_removed_ones_are_pruned() {
+        let owner = Principal::from_slice(&[4]);
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        do_insert(&Shoe {
+            id: 2,
+            deleted_at: Some(1),
+            ..Default::default()
+        });
+        assert!(matches!(
+            _add_to_wishlist_as(2, owner),
+            Err(Error::ShoeDeleted { .. })
+        ));
+        assert!(_add_to_wishlist_as(1, owner).is_ok());
+        SHOE_STORAGE.with(|service| service.borrow_mut().remove(&1));
+        assert!(_wishlist_of(owner).is_empty());
+    }
+
+    #[test]
+    fn newest_shoes_are_the_owners_latest_first() {
+        for (id, owner, created_at) in [(1, "a", 10), (2, "b", 30), (3, "a", 20), (4, "a", 5)] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                created_at: Timestamp(created_at),
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&_newest_shoes_of("a", 2)), vec![3, 1]);
+        assert_eq!(ids_of(&_newest_shoes_of("a", 10)), vec![3, 1, 4]);
+    }
+
+    #[test]
+    fn upcoming_restocks_are_future_listed_dates_soonest_first() {
+        for (id, restock_date, deleted_at) in [
+            (1, Some(300), None),
+            (2, Some(100), None),
+            (3, Some(50), None),
+            (4, None, None),
+            (5, Some(200), Some(1)),
+        ] {
+            do_insert(&Shoe {
+                id,
+                restock_date,
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&_upcoming_restocks(50)), vec![2, 1]);
+    }
+
+    #[test]
+    fn cas_quantity_only_applies_when_the_quantity_is_unchanged() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            quantity: 5,
+            ..Default::default()
+        });
+        assert!(matches!(
+            _cas_quantity_as(1, 4, 3, owner, 10),
+            Err(Error::QuantityMismatch { current: 5, .. })
+        ));
+        assert!(matches!(
+            _cas_quantity_as(1, 5, 3, Principal::from_slice(&[2]), 10),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(matches!(
+            _cas_quantity_as(1, 5, -1, owner, 10),
+            Err(Error::ValidationError { .. })
+        ));
+        let shoe = _cas_quantity_as(1, 5, 0, owner, 10).ok().unwrap();
+        assert_eq!((shoe.quantity, shoe.updated_at), (0, Some(Timestamp(10))));
+    }
+
+    #[test]
+    fn seller_listing_counts_skip_deleted_shoes() {
+        let (a, b) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        for (id, owner, deleted_at) in [(1, a, None), (2, b, None), (3, b, None), (4, a, Some(1))] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        assert_eq!(seller_listing_counts(), vec![(b, 2), (a, 1)]);
+    }
+
+    #[test]
+    fn categories_must_be_on_a_non_empty_allowlist() {
+        assert!(validate_category("boots").is_ok());
+        ALLOWED_CATEGORIES.with(|service| {
+            service
+                .borrow_mut()
+                .insert(StorableString("sneakers".to_string()), ())
+        });
+        assert!(validate_category(" Sneakers ").is_ok());
+        assert!(matches!(
+            validate_category("boots"),
+            Err(Error::ValidationError { .. })
+        ));
+    }
+
+    #[test]
+    fn shoe_changed_since_compares_the_last_change() {
+        do_insert(&Shoe {
+            id: 1,
+            created_at: Timestamp(10),
+            ..Default::default()
+        });
+        assert!(shoe_changed_since(1, 9).ok().flatten().is_some());
+        assert!(shoe_changed_since(1, 10).ok().flatten().is_none());
+        do_insert(&Shoe {
+            id: 1,
+            created_at: Timestamp(10),
+            updated_at: Some(Timestamp(20)),
+            ..Default::default()
+        });
+        assert!(shoe_changed_since(1, 10).ok().flatten().is_some());
+        assert!(shoe_changed_since(2, 0).is_err());
+    }
+
+    #[test]
+    fn discount_percent_rounds_to_a_whole_percentage() {
+        let shoe = Shoe {
+            price: 100,
+            sale_price: Some(67),
+            ..Default::default()
+        };
+        let third_off = Shoe {
+            price: 3,
+            sale_price: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(_discount_percent(&shoe), Some(33));
+        assert_eq!(_discount_percent(&third_off), Some(33));
+        assert_eq!(_discount_percent(&Shoe::default()), None);
+    }
+
+    #[test]
+    fn shoes_without_a_positive_price_are_invalid() {
+        for (id, price) in [(1, 50), (2, 0), (3, -5)] {
+            do_insert(&Shoe {
+                id,
+                price,
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&get_invalid_price_shoes()), vec![2, 3]);
+    }
+
+    #[test]
+    fn normalize_size_collapses_equivalent_spellings() {
+        assert_eq!(normalize_size("US 10.0"), "us 10");
+        assert_eq!(normalize_size("10 US"), "us 10");
+        assert_eq!(normalize_size("us10"), "us 10");
+        assert_eq!(normalize_size("EU 44.5"), "eu 44.5");
+        assert_eq!(normalize_size("10.0"), "10");
+    }
+
+    #[test]
+    fn normalize_size_keeps_other_sizes_as_lowercase_words() {
+        assert_eq!(normalize_size("  Extra   Large "), "extra large");
+        assert_eq!(normalize_size("0"), "0");
+        assert_eq!(normalize_size(""), "");
+    }
+
+    impl Storable for BaselineShoe {
+        fn to_bytes(&self) -> Cow<'_, [u8]> {
+            Cow::Owned(Encode!(self).unwrap())
+        }
+
+        fn from_bytes(_bytes: Cow<[u8]>) -> Self {
+            unreachable!("baseline shoes are only written")
+        }
+    }
+
+    impl BoundedStorable for BaselineShoe {
+        const MAX_SIZE: u32 = Shoe::MAX_SIZE;
+        const IS_FIXED_SIZE: bool = false;
+    }
+
+    #[test]
+    fn legacy_likes_move_to_the_likes_store_once() {
+        let liker = Principal::from_slice(&[2]);
+        let mut legacy: StableBTreeMap<u64, BaselineShoe, Memory> =
+            StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1))));
+        legacy.insert(
+            7,
+            BaselineShoe {
+                owner: Principal::anonymous().to_string(),
+                id: 7,
+                name: "Air Max".to_string(),
+                size: "US 10".to_string(),
+                shoe_url: "https://example.com/air-max".to_string(),
+                price: 120,
+                quantity: 3,
+                like: 1,
+                liked_by: vec![liker],
+                created_at: 42,
+                updated_at: None,
+            },
+        );
+        _migrate_legacy_likes();
+        assert_eq!(_shoe_likes(7), vec![(liker, 42)]);
+        assert_eq!(get_likers(7, 0, 10).ok().unwrap(), vec![liker]);
+        assert_eq!(_get_shoe(&7).unwrap().like, 1);
+
+        _unlike_shoe_as(7, liker).ok();
+        _migrate_legacy_likes();
+        assert!(_shoe_likes(7).is_empty());
+    }
+
+    #[test]
+    fn likes_in_range_counts_likes_by_their_time() {
+        do_insert(&Shoe {
+            id: 1,
+            ..Default::default()
+        });
+        for (n, liked_at) in [(1, 5), (2, 10), (3, 15)] {
+            assert!(_like_shoe_as(1, Principal::from_slice(&[n]), liked_at).is_ok());
+        }
+        assert_eq!(likes_in_range(5, 15), 2);
+        assert_eq!(likes_in_range(0, 100), 3);
+        assert_eq!(get_like_limit(), u64::MAX);
+    }
+
+    #[test]
+    fn best_sellers_rank_listed_shoes_by_units_sold() {
+        let buyer = Principal::from_slice(&[2]);
+        for id in 1..=4 {
+            do_insert(&Shoe {
+                id,
+                quantity: 10,
+                deleted_at: (id == 4).then_some(1),
+                sold: (id == 4).then_some(50),
+                ..Default::default()
+            });
+        }
+        assert!(_purchase_shoe_as(1, 2, buyer, 0).is_ok());
+        assert!(_purchase_shoe_as(2, 3, buyer, 0).is_ok());
+        assert!(_purchase_shoe_as(1, 2, buyer, 0).is_ok());
+        assert_eq!(_get_shoe(&1).unwrap().sold, Some(4));
+        assert_eq!(ids_of(&get_best_sellers(10)), vec![1, 2]);
+        assert_eq!(ids_of(&get_best_sellers(1)), vec![1]);
+    }
+
+    #[test]
+    fn interactors_are_the_likers_and_buyers_of_an_owners_shoes() {
+        let owner = Principal::from_slice(&[1]).to_string();
+        let (fan, buyer, stranger) = (
+            Principal::from_slice(&[2]),
+            Principal::from_slice(&[3]),
+            Principal::from_slice(&[4]),
+        );
+        for (id, shoe_owner) in [(1, owner.clone()), (2, "other".to_string())] {
+            do_insert(&Shoe {
+                id,
+                owner: shoe_owner,
+                quantity: 5,
+                ..Default::default()
+            });
+        }
+        assert!(_like_shoe_as(1, fan, 0).is_ok());
+        assert!(_like_shoe_as(1, buyer, 0).is_ok());
+        assert!(_purchase_shoe_as(1, 1, buyer, 0).is_ok());
+        assert!(_purchase_shoe_as(2, 1, stranger, 0).is_ok());
+        assert_eq!(_interactors_of(&owner), vec![fan, buyer]);
+    }
+
+    fn set_test_policy(policy: Policy) {
+        POLICY.with(|cell| cell.borrow_mut().set(policy)).ok();
+    }
+
+    #[test]
+    fn policy_rules_only_apply_once_enabled() {
+        let owner = Principal::from_slice(&[1]);
+        let shoe = Shoe {
+            owner: owner.to_string(),
+            ..Default::default()
+        };
+        assert!(_check_policy(PolicyAction::Like(&shoe), owner, 0).is_ok());
+        assert!(_check_policy(PolicyAction::AddShoe, Principal::anonymous(), 0).is_ok());
+        set_test_policy(Policy {
+            no_self_like: true,
+            no_anonymous: true,
+            ..Default::default()
+        });
+        assert!(matches!(
+            _check_policy(PolicyAction::Like(&shoe), owner, 0),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(_check_policy(PolicyAction::AddShoe, owner, 0).is_ok());
+        assert!(matches!(
+            _check_policy(PolicyAction::AddShoe, Principal::anonymous(), 0),
+            Err(Error::NotAuthorized { .. })
+        ));
+    }
+
+    #[test]
+    fn blocked_principals_are_rejected_while_the_blocklist_is_enabled() {
+        let blocked = Principal::from_slice(&[1]);
+        BLOCKLIST.with(|service| service.borrow_mut().insert(StorablePrincipal(blocked), ()));
+        assert!(_check_policy(PolicyAction::AddShoe, blocked, 0).is_ok());
+        set_test_policy(Policy {
+            blocklist_enabled: true,
+            ..Default::default()
+        });
+        assert!(matches!(
+            _check_policy(PolicyAction::AddShoe, blocked, 0),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(_check_policy(PolicyAction::AddShoe, Principal::from_slice(&[2]), 0).is_ok());
+    }
+
+    #[test]
+    fn rate_limit_window_restarts_after_a_minute() {
+        let caller = Principal::from_slice(&[1]);
+        set_test_policy(Policy {
+            max_actions_per_minute: Some(2),
+            ..Default::default()
+        });
+        assert!(_check_policy(PolicyAction::AddShoe, caller, 0).is_ok());
+        assert!(_check_policy(PolicyAction::AddShoe, caller, 1).is_ok());
+        assert!(matches!(
+            _check_policy(PolicyAction::AddShoe, caller, 2),
+            Err(Error::RateLimited { .. })
+        ));
+        assert!(_check_policy(PolicyAction::AddShoe, Principal::from_slice(&[2]), 2).is_ok());
+        assert!(_check_policy(PolicyAction::AddShoe, caller, RATE_LIMIT_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn seller_name_falls_back_to_the_principal() {
+        let (named, blank) = (Principal::from_slice(&[1]), Principal::from_slice(&[2]));
+        for (id, seller, display_name) in [(1, named, "Shoe Shop"), (2, blank, " ")] {
+            do_insert(&Shoe {
+                id,
+                owner: seller.to_string(),
+                ..Default::default()
+            });
+            SELLER_PROFILES.with(|service| {
+                service.borrow_mut().insert(
+                    StorablePrincipal(seller),
+                    SellerProfile {
+                        display_name: display_name.to_string(),
+                        return_policy: String::new(),
+                    },
+                )
+            });
+        }
+        assert_eq!(get_shoe_seller_name(1).ok().unwrap(), "Shoe Shop");
+        assert_eq!(get_shoe_seller_name(2).ok().unwrap(), blank.to_string());
+        assert!(get_shoe_seller_name(3).is_err());
+    }
+
+    #[test]
+    fn next_free_id_never_moves_the_counter_backwards() {
+        assert_eq!(_next_free_id(), 0);
+        for id in [3, 8] {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+        }
+        assert_eq!(_next_free_id(), 9);
+        ID_COUNTER.with(|counter| counter.borrow_mut().set(20)).ok();
+        assert_eq!(_next_free_id(), 20);
+    }
+
+    #[test]
+    fn resolved_ids_keep_their_order() {
+        for (id, deleted_at) in [(1, None), (2, Some(1)), (3, None)] {
+            do_insert(&Shoe {
+                id,
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        let resolved: Vec<Option<u64>> = resolve_shoe_ids(vec![3, 2, 9, 1])
+            .iter()
+            .map(|shoe| shoe.as_ref().map(|shoe| shoe.id))
+            .collect();
+        assert_eq!(resolved, vec![Some(3), None, None, Some(1)]);
+    }
+
+    #[test]
+    fn inventory_snapshot_totals_the_listed_stock() {
+        for (id, price, quantity, deleted_at) in [
+            (1, 100, 2, None),
+            (2, 50, 0, None),
+            (3, 80, -1, None),
+            (4, 70, 5, Some(1)),
+        ] {
+            do_insert(&Shoe {
+                id,
+                price,
+                quantity,
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        let snapshot = _inventory_snapshot(42);
+        assert_eq!(snapshot.taken_at, 42);
+        assert_eq!(snapshot.listings, 3);
+        assert_eq!(snapshot.total_units, 2);
+        assert_eq!(snapshot.total_value, 200);
+    }
+
+    #[test]
+    fn trending_weights_shape_the_score() {
+        let shoe = Shoe {
+            like: 4,
+            created_at: Timestamp(0),
+            ..Default::default()
+        };
+        let likes_only = TrendingWeights {
+            like_weight: 2.0,
+            recency_weight: 0.0,
+        };
+        assert_eq!(_trending_score(&shoe, 3_000_000_000, likes_only), 16.0);
+        let recency_heavy = TrendingWeights {
+            like_weight: 1.0,
+            recency_weight: 2.0,
+        };
+        assert_eq!(_trending_score(&shoe, 1_000_000_000, recency_heavy), 1.0);
+    }
+
+    #[test]
+    fn trending_ranking_reads_the_stored_weights() {
+        for (id, like, created_at) in [(1, 10, 0), (2, 1, 9_000_000_000)] {
+            do_insert(&Shoe {
+                id,
+                like,
+                created_at: Timestamp(created_at),
+                ..Default::default()
+            });
+        }
+        let now = 10_000_000_000;
+        assert_eq!(ids_of(&_compute_trending(2, now)), vec![1, 2]);
+        TRENDING_WEIGHTS
+            .with(|cell| {
+                cell.borrow_mut().set(TrendingWeights {
+                    like_weight: 1.0,
+                    recency_weight: 2.0,
+                })
+            })
+            .ok();
+        assert_eq!(ids_of(&_compute_trending(2, now)), vec![2, 1]);
+    }
+
+    #[test]
+    fn an_owners_shoes_sort_by_the_requested_field() {
+        for (id, owner, price) in [(1, "a", 30), (2, "a", 10), (3, "b", 20), (4, "a", 20)] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                price,
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            ids_of(&_sorted_shoes_of("a", SortBy::Price, false)),
+            vec![2, 4, 1]
+        );
+        assert_eq!(
+            ids_of(&_sorted_shoes_of("a", SortBy::Price, true)),
+            vec![1, 4, 2]
+        );
+    }
+
+    #[test]
+    fn soft_deleted_shoes_cant_be_changed_by_their_owner() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            quantity: 2,
+            deleted_at: Some(1),
+            ..Default::default()
+        });
+        assert!(_get_shoe_owned_by(1, owner).is_ok());
+        assert!(matches!(
+            _get_active_shoe_owned_by(1, owner),
+            Err(Error::ShoeDeleted { id: 1, .. })
+        ));
+        assert!(matches!(
+            _get_active_shoe_owned_by(1, Principal::from_slice(&[2])),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(matches!(
+            _restocked_shoe(1, 1, owner),
+            Err(Error::ShoeDeleted { .. })
+        ));
+        assert!(matches!(
+            _cas_quantity_as(1, 2, 3, owner, 0),
+            Err(Error::ShoeDeleted { .. })
+        ));
+    }
+
+    #[test]
+    fn sizes_sort_by_system_then_numerically() {
+        let mut sizes = vec!["us 10.5", "xl", "us 10", "10", "eu 44", "us 9.5"];
+        sizes.sort_by(|a, b| _compare_sizes(a, b));
+        assert_eq!(
+            sizes,
+            vec!["10", "eu 44", "us 9.5", "us 10", "us 10.5", "xl"]
+        );
+    }
+
+    #[test]
+    fn size_counts_group_in_stock_listings_by_normalized_size() {
+        for (id, size, quantity) in [
+            (1, "US 10.0", 1),
+            (2, "10 US", 2),
+            (3, "EU 44", 0),
+            (4, "xl", 1),
+        ] {
+            do_insert(&Shoe {
+                id,
+                size: size.to_string(),
+                quantity,
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            size_counts(),
+            vec![("us 10".to_string(), 2), ("xl".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn recategorize_moves_only_the_owners_listed_shoes() {
+        for (id, owner, category, deleted_at) in [
+            (1, "a", "Running", None),
+            (2, "a", "running", Some(1)),
+            (3, "b", "running", None),
+            (4, "a", "boots", None),
+        ] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                category: Some(category.to_string()),
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        assert_eq!(_recategorize("a", " RUNNING", "Trail", 7).ok(), Some(1));
+        let moved = _get_shoe(&1).unwrap();
+        assert_eq!(
+            (moved.category.as_deref(), moved.updated_at),
+            (Some("trail"), Some(Timestamp(7)))
+        );
+        assert_eq!(_get_shoe(&2).unwrap().category.as_deref(), Some("running"));
+        assert_eq!(_get_shoe(&3).unwrap().category.as_deref(), Some("running"));
+
+        ALLOWED_CATEGORIES.with(|service| {
+            service
+                .borrow_mut()
+                .insert(StorableString("trail".to_string()), ())
+        });
+        assert!(matches!(
+            _recategorize("a", "boots", "sandals", 8),
+            Err(Error::ValidationError { .. })
+        ));
+        assert_eq!(_get_shoe(&4).unwrap().category.as_deref(), Some("boots"));
+    }
+
+    #[test]
+    fn shoes_near_price_rank_by_distance_within_the_tolerance() {
+        for (id, price, quantity) in [
+            (1, 100, 1),
+            (2, 95, 1),
+            (3, 108, 1),
+            (4, 120, 1),
+            (5, 101, 0),
+        ] {
+            do_insert(&Shoe {
+                id,
+                price,
+                quantity,
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&shoes_near_price(100, 10, 10)), vec![1, 2, 3]);
+        assert_eq!(ids_of(&shoes_near_price(100, 10, 2)), vec![1, 2]);
+    }
+
+    #[test]
+    fn like_rate_is_the_share_of_interactors_who_liked() {
+        let (fan, buyer, both) = (
+            Principal::from_slice(&[2]),
+            Principal::from_slice(&[3]),
+            Principal::from_slice(&[4]),
+        );
+        do_insert(&Shoe {
+            id: 1,
+            quantity: 5,
+            ..Default::default()
+        });
+        assert_eq!(like_rate(1).ok(), Some(0.0));
+        assert!(_like_shoe_as(1, fan, 0).is_ok());
+        assert!(_like_shoe_as(1, both, 0).is_ok());
+        assert!(_purchase_shoe_as(1, 1, buyer, 0).is_ok());
+        assert!(_purchase_shoe_as(1, 1, both, 0).is_ok());
+        assert!(_purchase_shoe_as(1, 1, buyer, 0).is_ok());
+        assert_eq!(like_rate(1).ok(), Some(2.0 / 3.0));
+        assert!(matches!(like_rate(2), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn a_closed_store_rejects_changes() {
+        assert!(is_store_open());
+        assert!(_ensure_store_open().is_ok());
+        STORE_OPEN.with(|cell| cell.borrow_mut().set(0)).ok();
+        assert!(!is_store_open());
+        assert!(matches!(
+            _ensure_store_open(),
+            Err(Error::StoreClosed { .. })
+        ));
+    }
+
+    #[test]
+    fn an_owners_shoes_sort_by_stock_value() {
+        for (id, owner, price, quantity) in [
+            (1, "a", 10, 5),
+            (2, "a", 100, 1),
+            (3, "b", 1, 1),
+            (4, "a", 20, -1),
+        ] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                price,
+                quantity,
+                ..Default::default()
+            });
+        }
+        let ranked: Vec<(u64, u64)> = _shoes_by_value_of("a", true)
+            .iter()
+            .map(|(shoe, value)| (shoe.id, *value))
+            .collect();
+        assert_eq!(ranked, vec![(2, 100), (1, 50), (4, 0)]);
+    }
+
+    #[test]
+    fn shoes_without_a_condition_default_to_new() {
+        for (id, condition) in [
+            (1, None),
+            (2, Some(ShoeCondition::Used)),
+            (3, Some(ShoeCondition::New)),
+        ] {
+            do_insert(&Shoe {
+                id,
+                condition,
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            ids_of(&get_shoes_by_condition(ShoeCondition::New)),
+            vec![1, 3]
+        );
+        _default_conditions();
+        assert_eq!(_get_shoe(&1).unwrap().condition, Some(ShoeCondition::New));
+        assert_eq!(_get_shoe(&2).unwrap().condition, Some(ShoeCondition::Used));
+        assert_eq!(
+            ids_of(&get_shoes_by_condition(ShoeCondition::Used)),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn identical_shoe_requires_the_same_condition() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            name: "Air Max".to_string(),
+            condition: Some(ShoeCondition::Used),
+            ..Default::default()
+        });
+        let payload = ShoePayload {
+            name: "Air Max".to_string(),
+            condition: Some(ShoeCondition::Used),
+            ..Default::default()
+        };
+        assert_eq!(_find_identical_shoe(&payload, owner).map(|s| s.id), Some(1));
+        let new_pair = ShoePayload {
+            condition: None,
+            ..payload
+        };
+        assert!(_find_identical_shoe(&new_pair, owner).is_none());
+    }
+
+    #[test]
+    fn random_featured_shoe_picks_only_listed_featured_shoes() {
+        assert!(_pick_featured(7).is_none());
+        for id in 1..=3 {
+            do_insert(&Shoe {
+                id,
+                deleted_at: if id == 2 { Some(1) } else { None },
+                ..Default::default()
+            });
+            FEATURED_SHOES.with(|service| service.borrow_mut().insert(id, ()));
+        }
+        for now in 0..50 {
+            let picked = _pick_featured(now).unwrap().id;
+            assert!(picked == 1 || picked == 3);
+        }
+        assert_eq!(
+            _pick_featured(11).map(|s| s.id),
+            _pick_featured(11).map(|s| s.id)
+        );
+    }
+
+    #[test]
+    fn shoes_by_brand_ignore_case_and_skip_deleted_shoes() {
+        for (id, brand, deleted_at) in [
+            (1, Some("nike"), None),
+            (2, Some("adidas"), None),
+            (3, Some("nike"), Some(1)),
+            (4, None, None),
+        ] {
+            do_insert(&Shoe {
+                id,
+                brand: brand.map(str::to_string),
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&get_shoes_by_brand(" NIKE ".to_string())), vec![1]);
+        assert!(get_shoes_by_brand(" ".to_string()).is_empty());
+        assert_eq!(distinct_brands(), vec!["adidas", "nike"]);
+        do_insert(&Shoe {
+            id: 1,
+            brand: Some("puma".to_string()),
+            ..Default::default()
+        });
+        assert!(get_shoes_by_brand("nike".to_string()).is_empty());
+        assert_eq!(distinct_brands(), vec!["adidas", "puma"]);
+    }
+
+    #[test]
+    fn overflowed_brands_fall_back_to_a_scan_until_reindexed() {
+        let key = StorableString("nike".to_string());
+        let full = IdList((1_000..1_000 + MAX_IDS_PER_INDEX_KEY as u64).collect());
+        BRAND_INDEX.with(|service| service.borrow_mut().insert(key.clone(), full));
+        do_insert(&Shoe {
+            id: 1,
+            brand: Some("nike".to_string()),
+            ..Default::default()
+        });
+        _move_in_index(
+            &BRAND_INDEX,
+            &BRAND_INDEX_OVERFLOW,
+            1_000,
+            vec!["nike".to_string()],
+            Vec::new(),
+        );
+        assert_eq!(ids_of(&get_shoes_by_brand("Nike".to_string())), vec![1]);
+        _reindex_shoes();
+        assert!(!BRAND_INDEX_OVERFLOW.with(|service| service.borrow().contains_key(&key)));
+        assert_eq!(
+            BRAND_INDEX
+                .with(|service| service.borrow().get(&key))
+                .map(|ids| ids.0),
+            Some(vec![1])
+        );
+        assert_eq!(ids_of(&get_shoes_by_brand("Nike".to_string())), vec![1]);
+    }
+
+    // the payload as sent by clients built before schema versions existed
+    #[derive(candid::CandidType, Serialize)]
+    struct BaselinePayload {
+        name: String,
+        size: String,
+        shoe_url: String,
+        price: i16,
+        quantity: i16,
+    }
+
+    #[test]
+    fn version_1_payloads_decode_and_drop_the_newer_fields() {
+        let bytes = Encode!(&BaselinePayload {
+            name: "Air Max".to_string(),
+            size: "US 10".to_string(),
+            shoe_url: "https://example.com/air-max".to_string(),
+            price: 120,
+            quantity: 3,
+        })
+        .unwrap();
+        let payload = Decode!(&bytes, ShoePayload).unwrap();
+        assert_eq!(payload.schema_version, None);
+        assert_eq!(payload.category, None);
+
+        let payload = negotiate_schema(ShoePayload {
+            sku: Some("AM-10".to_string()),
+            images: Some(vec!["https://example.com/a.png".to_string()]),
+            condition: Some(ShoeCondition::Used),
+            ..payload
+        })
+        .ok()
+        .unwrap();
+        assert_eq!(payload.schema_version, Some(CURRENT_SCHEMA_VERSION));
+        assert_eq!(payload.name, "Air Max");
+        assert_eq!(payload.sku, None);
+        assert_eq!(payload.images, None);
+        assert_eq!(payload.condition, None);
+    }
+
+    #[test]
+    fn current_payloads_are_kept_and_newer_ones_rejected() {
+        let payload = negotiate_schema(ShoePayload {
+            schema_version: Some(CURRENT_SCHEMA_VERSION),
+            sku: Some("AM-10".to_string()),
+            ..Default::default()
+        })
+        .ok()
+        .unwrap();
+        assert_eq!(payload.sku.as_deref(), Some("AM-10"));
+        assert!(matches!(
+            negotiate_schema(ShoePayload {
+                schema_version: Some(CURRENT_SCHEMA_VERSION + 1),
+                ..Default::default()
+            }),
+            Err(Error::UnsupportedSchema { version, .. }) if version == CURRENT_SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn orphan_shoes_can_be_assigned_once() {
+        let owner = Principal::from_slice(&[5]);
+        for (id, shoe_owner) in [(1, ""), (2, " "), (3, "seller")] {
+            do_insert(&Shoe {
+                id,
+                owner: shoe_owner.to_string(),
+                ..Default::default()
+            });
+        }
+        assert_eq!(ids_of(&_orphan_shoes()), vec![1, 2]);
+        assert!(matches!(
+            _assign_orphan(1, Principal::anonymous(), 9),
+            Err(Error::ValidationError { .. })
+        ));
+        let shoe = _assign_orphan(1, owner, 9).ok().unwrap();
+        assert_eq!(shoe.owner, owner.to_string());
+        assert_eq!(shoe.updated_at, Some(Timestamp(9)));
+        assert!(matches!(
+            _assign_orphan(1, owner, 10),
+            Err(Error::ValidationError { .. })
+        ));
+        assert!(matches!(
+            _assign_orphan(3, owner, 10),
+            Err(Error::ValidationError { .. })
+        ));
+        assert!(matches!(
+            _assign_orphan(4, owner, 10),
+            Err(Error::NotFound { .. })
+        ));
+        assert_eq!(ids_of(&_orphan_shoes()), vec![2]);
+    }
+
+    fn tags(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn validate_tags_normalizes_and_dedupes() {
+        assert_eq!(
+            validate_tags(&tags(&[" Running ", "running", "", "Trail"])).ok(),
+            Some(tags(&["running", "trail"]))
+        );
+    }
+
+    #[test]
+    fn validate_tags_limits_length_and_count() {
+        let long_tag = "a".repeat(MAX_TAG_LEN + 1);
+        assert!(validate_tags(&[long_tag]).is_err());
+        let too_many: Vec<String> = (0..=MAX_TAGS).map(|i| format!("tag{}", i)).collect();
+        assert!(validate_tags(&too_many).is_err());
+        let mut with_duplicates: Vec<String> = (0..MAX_TAGS).map(|i| format!("tag{}", i)).collect();
+        with_duplicates.push("TAG0".to_string());
+        assert_eq!(
+            validate_tags(&with_duplicates).ok().map(|tags| tags.len()),
+            Some(MAX_TAGS)
+        );
+    }
+
+    #[test]
+    fn the_largest_tag_list_fits_its_bound() {
+        assert!(validate_tags(&["\u{1F45F}".repeat(MAX_TAG_LEN)]).is_ok());
+        let largest = TagList(
+            (0..MAX_TAGS)
+                .map(|i| format!("{}{}", i, "\u{1F45F}".repeat(MAX_TAG_LEN - 1)))
+                .collect(),
+        );
+        assert!(largest.to_bytes().len() <= TagList::MAX_SIZE as usize);
+    }
+
+    #[test]
+    fn full_text_search_ranks_by_matched_words() {
+        for (id, name, category) in [
+            (1, "Trail Runner", "running"),
+            (2, "Court Classic", "tennis"),
+            (3, "Road Racer", "running"),
+        ] {
+            do_insert(&Shoe {
+                id,
+                name: name.to_string(),
+                category: Some(category.to_string()),
+                ..Default::default()
+            });
+        }
+        _set_tags(3, tags(&["trail", "waterproof"]));
+        assert_eq!(
+            ids_of(&full_text_search("TRAIL water".to_string(), 10)),
+            vec![3, 1]
+        );
+        assert_eq!(ids_of(&full_text_search("run".to_string(), 10)), vec![1, 3]);
+        assert_eq!(ids_of(&full_text_search("run".to_string(), 1)), vec![1]);
+        assert!(full_text_search(" ".to_string(), 10).is_empty());
+        _set_tags(3, Vec::new());
+        assert!(SHOE_TAGS.with(|service| service.borrow().get(&3).is_none()));
+        assert!(full_text_search("waterproof".to_string(), 10).is_empty());
+    }
+
+    #[test]
+    fn merging_shoes_combines_stock_sales_and_likes() {
+        let owner = Principal::from_slice(&[1]);
+        let both = Principal::from_slice(&[2]);
+        let one = Principal::from_slice(&[3]);
+        for (id, quantity, sold) in [(1, 4, Some(2)), (2, 3, None)] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                quantity,
+                sold,
+                ..Default::default()
+            });
+            assert!(_like_shoe_as(id, both, 0).is_ok());
+        }
+        assert!(_like_shoe_as(2, one, 5).is_ok());
+
+        let kept = _merge_shoes_as(1, 2, owner, 9).ok().unwrap();
+        assert_eq!(kept.quantity, 7);
+        assert_eq!(kept.sold, Some(2));
+        assert_eq!(kept.like, 2);
+        assert_eq!(kept.updated_at, Some(Timestamp(9)));
+        assert!(_get_shoe(&2).is_none());
+        assert_eq!(_shoe_likes(1), vec![(both, 0), (one, 5)]);
+        assert!(_shoe_likes(2).is_empty());
+        assert_eq!(get_top_likers(10), vec![(both, 1), (one, 1)]);
+    }
+
+    #[test]
+    fn merging_shoes_needs_two_active_shoes_of_the_caller() {
+        let owner = Principal::from_slice(&[1]);
+        for (id, quantity) in [(1, i16::MAX), (2, 1)] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                quantity,
+                ..Default::default()
+            });
+        }
+        do_insert(&Shoe {
+            id: 3,
+            owner: Principal::from_slice(&[2]).to_string(),
+            ..Default::default()
+        });
+        assert!(matches!(
+            _merge_shoes_as(1, 1, owner, 0),
+            Err(Error::ValidationError { .. })
+        ));
+        assert!(matches!(
+            _merge_shoes_as(1, 3, owner, 0),
+            Err(Error::NotAuthorized { .. })
+        ));
+        assert!(matches!(
+            _merge_shoes_as(1, 2, owner, 0),
+            Err(Error::ValidationError { .. })
+        ));
+        assert!(_get_shoe(&2).is_some());
+    }
+
+    #[test]
+    fn removing_a_shoe_takes_its_likes_off_the_likers() {
+        let fan = Principal::from_slice(&[1]);
+        for id in [1, 2] {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+            assert!(_like_shoe_as(id, fan, 0).is_ok());
+        }
+        _set_tags(1, vec!["trail".to_string()]);
+        assert!(_remove_shoe(1).is_some());
+        assert_eq!(get_top_likers(10), vec![(fan, 1)]);
+        assert!(_get_tags(1).is_empty());
+        assert!(_remove_shoe(1).is_none());
+    }
+
+    #[test]
+    fn price_history_charts_each_recorded_price() {
+        do_insert(&Shoe {
+            id: 1,
+            price: 100,
+            created_at: Timestamp(1),
+            ..Default::default()
+        });
+        assert_eq!(_price_history(1, 3).ok(), Some(vec![(1, 100), (3, 100)]));
+        for (price, updated_at) in [(120, 5), (90, 8)] {
+            do_insert(&Shoe {
+                id: 1,
+                price,
+                created_at: Timestamp(1),
+                updated_at: Some(Timestamp(updated_at)),
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            _price_history(1, 10).ok(),
+            Some(vec![(1, 100), (5, 120), (8, 90), (10, 90)])
+        );
+        assert!(matches!(_price_history(2, 10), Err(Error::NotFound { .. })));
+    }
+
+    #[test]
+    fn likes_wait_out_the_cooldown() {
+        let fan = Principal::from_slice(&[1]);
+        for id in 1..=3 {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+        }
+        assert!(_like_shoe_as(1, fan, 0).is_ok());
+        assert!(_like_shoe_as(2, fan, 1).is_ok());
+        LIKE_COOLDOWN.with(|cell| cell.borrow_mut().set(10)).ok();
+        let second = 1_000_000_000;
+        assert!(matches!(
+            _like_shoe_as(3, fan, 5 * second),
+            Err(Error::RateLimited { .. })
+        ));
+        assert!(_like_shoe_as(3, Principal::from_slice(&[2]), 5 * second).is_ok());
+        assert!(_like_shoe_as(3, fan, 10 * second + 1).is_ok());
+        assert_eq!(_get_shoe(&3).unwrap().like, 2);
+    }
+
+    #[test]
+    fn my_shoes_in_category_ignore_case_and_other_owners() {
+        for (id, owner, category) in [
+            (1, "seller", Some("running")),
+            (2, "seller", Some("tennis")),
+            (3, "other", Some("running")),
+            (4, "seller", Some("Running ")),
+            (5, "seller", None),
+        ] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                category: category.map(str::to_string),
+                ..Default::default()
+            });
+        }
+        assert_eq!(
+            ids_of(&_shoes_in_category_of("seller", " RUNNING")),
+            vec![1, 4]
+        );
+        assert_eq!(ids_of(&_shoes_in_category_of("seller", "")), vec![5]);
+    }
+
+    #[test]
+    fn size_variants_copy_the_listing_but_not_its_sku() {
+        let source = Shoe {
+            id: 1,
+            name: "Air Max".to_string(),
+            sku: Some("AM-10".to_string()),
+            brand: Some("nike".to_string()),
+            category: Some("running".to_string()),
+            size: "US 10".to_string(),
+            shoe_url: "https://example.com/air-max".to_string(),
+            price: 120,
+            quantity: 4,
+            ..Default::default()
+        };
+        let payload = _size_variant_payload(source, "US 11".to_string(), 2);
+        assert_eq!(payload.schema_version, Some(CURRENT_SCHEMA_VERSION));
+        assert_eq!(payload.name, "Air Max");
+        assert_eq!(payload.sku, None);
+        assert_eq!(payload.brand.as_deref(), Some("nike"));
+        assert_eq!(payload.category.as_deref(), Some("running"));
+        assert_eq!(payload.size, "US 11");
+        assert_eq!(payload.shoe_url, "https://example.com/air-max");
+        assert_eq!((payload.price, payload.quantity), (120, 2));
+        assert!(validate_shoe_payload(&payload).is_ok());
+    }
+
+    #[test]
+    fn size_matrix_lists_the_sizes_of_one_product() {
+        for (id, name, size, deleted_at) in [
+            (1, "Air Max", "US 10", None),
+            (2, "air  max", "EU 44", None),
+            (3, "Air Max", "US 9.5", None),
+            (4, "Air Max 90", "US 10", None),
+            (5, "Air Max", "US 11", Some(1)),
+        ] {
+            do_insert(&Shoe {
+                id,
+                name: name.to_string(),
+                size: size.to_string(),
+                quantity: id as i16,
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        let matrix = size_matrix(" AIR MAX ".to_string());
+        assert_eq!(
+            matrix
+                .iter()
+                .map(|variant| (variant.size.as_str(), variant.shoe_id, variant.quantity))
+                .collect::<Vec<_>>(),
+            vec![("eu 44", 2, 2), ("us 9.5", 3, 3), ("us 10", 1, 1)]
+        );
+    }
+
+    #[test]
+    fn ranked_queries_are_capped_at_the_max_page_size() {
+        for id in 1..=4 {
+            do_insert(&Shoe {
+                id,
+                price: 100,
+                sale_price: Some(100 - id as i16),
+                quantity: 1,
+                sold: Some(id),
+                ..Default::default()
+            });
+            assert!(_like_shoe_as(id, Principal::from_slice(&[id as u8]), 0).is_ok());
+        }
+        TRENDING_CACHE.with(|cache| {
+            *cache.borrow_mut() = Some(TrendingCache {
+                computed_at: 0,
+                ids: vec![4, 3, 2, 1],
+            })
+        });
+        MAX_PAGE_SIZE.with(|cell| cell.borrow_mut().set(2)).ok();
+        assert_eq!(ids_of(&get_best_deals(10)), vec![4, 3]);
+        assert_eq!(ids_of(&get_best_sellers(10)), vec![4, 3]);
+        assert_eq!(get_top_likers(10).len(), 2);
+        assert_eq!(
+            get_related_shoes(1, 10).ok().map(|shoes| shoes.len()),
+            Some(2)
+        );
+        assert_eq!(ids_of(&get_trending_shoes(10)), vec![4, 3]);
+    }
+
+    #[test]
+    fn actions_by_pages_through_one_actors_entries() {
+        let actor = Principal::from_slice(&[1]);
+        let other = Principal::from_slice(&[2]);
+        _audit_as(actor, "add_shoe", Some(1), 5);
+        _audit_as(other, "like_shoe", Some(1), 6);
+        _audit_as(actor, "like_shoe", Some(2), 7);
+        _audit_as(actor, "transfer_shoe", None, 8);
+        let actions = |offset, limit| {
+            _actions_by(actor, offset, limit)
+                .into_iter()
+                .map(|entry| (entry.action, entry.shoe_id, entry.timestamp))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            actions(0, 10),
+            vec![
+                ("add_shoe".to_string(), Some(1), 5),
+                ("like_shoe".to_string(), Some(2), 7),
+                ("transfer_shoe".to_string(), None, 8),
+            ]
+        );
+        assert_eq!(actions(1, 1), vec![("like_shoe".to_string(), Some(2), 7)]);
+        assert!(_actions_by(Principal::from_slice(&[3]), 0, 10).is_empty());
+    }
+
+    #[test]
+    fn the_audit_log_keeps_only_the_latest_entries() {
+        let actor = Principal::from_slice(&[1]);
+        for now in 0..=MAX_AUDIT_LOG_LEN {
+            _audit_as(actor, "soft_delete_shoe", Some(u64::MAX), now);
+        }
+        assert_eq!(
+            AUDIT_LOG.with(|service| service.borrow().len()),
+            MAX_AUDIT_LOG_LEN
+        );
+        assert_eq!(_actions_by(actor, 0, 1)[0].timestamp, 1);
+        let entry = AUDIT_LOG.with(|service| service.borrow().iter().next().unwrap().1);
+        assert!(entry.to_bytes().len() <= AuditEntry::MAX_SIZE as usize);
+    }
+
+    #[test]
+    fn drafts_are_not_listed() {
+        let draft = Shoe {
+            published: Some(false),
+            ..Default::default()
+        };
+        let published = Shoe {
+            published: Some(true),
+            ..Default::default()
+        };
+        // stored before drafts existed
+        let legacy = Shoe {
+            published: None,
+            ..Default::default()
+        };
+        assert!(!_is_listed(&draft));
+        assert!(matches!(
+            _ensure_listed(&draft),
+            Err(Error::NotFound { .. })
+        ));
+        assert!(_is_listed(&published));
+        assert!(_is_listed(&legacy));
+    }
+
+    #[test]
+    fn drafts_have_their_own_status_and_stay_out_of_get_shoes() {
+        let draft = Shoe {
+            id: 1,
+            quantity: 3,
+            published: Some(false),
+            ..Default::default()
+        };
+        do_insert(&draft);
+        do_insert(&Shoe {
+            id: 2,
+            quantity: 3,
+            ..Default::default()
+        });
+        assert_eq!(_shoe_status(&draft), ShoeStatus::Draft);
+        assert_eq!(ids_of(&get_shoes()), vec![2]);
+    }
+
+    #[test]
+    fn version_2_payloads_are_published() {
+        let payload = negotiate_schema(ShoePayload {
+            schema_version: Some(2),
+            published: Some(false),
+            ..Default::default()
+        })
+        .ok()
+        .unwrap();
+        assert_eq!(payload.schema_version, Some(CURRENT_SCHEMA_VERSION));
+        assert_eq!(payload.published, None);
+    }
+
+    #[test]
+    fn identical_shoes_merge_only_with_the_same_published_state() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            name: "Air Max".to_string(),
+            published: Some(false),
+            ..Default::default()
+        });
+        let payload = |published| ShoePayload {
+            name: "Air Max".to_string(),
+            published,
+            ..Default::default()
+        };
+        assert!(_find_identical_shoe(&payload(None), owner).is_none());
+        assert_eq!(
+            _find_identical_shoe(&payload(Some(false)), owner).map(|shoe| shoe.id),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn publishing_toggles_the_flag_and_audits_only_changes() {
+        let owner = Principal::from_slice(&[1]);
+        do_insert(&Shoe {
+            id: 1,
+            owner: owner.to_string(),
+            published: Some(false),
+            ..Default::default()
+        });
+        let shoe = _set_published_as(1, true, owner, 5).ok().unwrap();
+        assert_eq!(shoe.published, Some(true));
+        assert_eq!(shoe.updated_at, Some(Timestamp(5)));
+        assert!(_set_published_as(1, true, owner, 6).is_ok());
+        let actions: Vec<_> = _actions_by(owner, 0, 10)
+            .into_iter()
+            .map(|entry| entry.action)
+            .collect();
+        assert_eq!(actions, vec!["publish_shoe".to_string()]);
+        assert!(matches!(
+            _set_published_as(1, false, Principal::from_slice(&[2]), 7),
+            Err(Error::NotAuthorized { .. })
+        ));
+    }
+
+    #[test]
+    fn shoes_published_between_covers_a_sub_window() {
+        let owner = Principal::from_slice(&[1]);
+        for id in 1..=4 {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                published: Some(false),
+                ..Default::default()
+            });
+        }
+        for (id, now) in [(3, 30), (1, 10), (2, 20), (4, 40)] {
+            assert!(_set_published_as(id, true, owner, now).is_ok());
+        }
+        assert_eq!(ids_of(&get_shoes_published_between(15, 30)), vec![2, 3]);
+        assert!(_set_published_as(2, false, owner, 50).is_ok());
+        assert_eq!(ids_of(&get_shoes_published_between(15, 30)), vec![3]);
+        assert!(get_shoes_published_between(41, 100).is_empty());
+    }
+
+    #[test]
+    fn published_at_is_set_only_when_a_shoe_goes_live() {
+        let mut shoe = Shoe {
+            published: Some(false),
+            ..Default::default()
+        };
+        _mark_published(&mut shoe, false, 5);
+        assert_eq!(shoe.published_at, None);
+        _mark_published(&mut shoe, true, 6);
+        assert_eq!(shoe.published_at, Some(6));
+        _mark_published(&mut shoe, true, 7);
+        assert_eq!(shoe.published_at, Some(6));
+    }
+
+    #[test]
+    fn neighbors_skip_unlisted_shoes_and_stop_at_the_ends() {
+        for id in 1..=5 {
+            do_insert(&Shoe {
+                id,
+                deleted_at: (id == 2).then_some(1),
+                published: Some(id != 4),
+                ..Default::default()
+            });
+        }
+        let neighbors = |id| {
+            get_shoe_with_neighbors(id)
+                .ok()
+                .map(|nav| (nav.prev, nav.current.id, nav.next))
+        };
+        assert_eq!(neighbors(1), Some((None, 1, Some(3))));
+        assert_eq!(neighbors(3), Some((Some(1), 3, Some(5))));
+        assert_eq!(neighbors(5), Some((Some(3), 5, None)));
+        assert!(matches!(
+            get_shoe_with_neighbors(6),
+            Err(Error::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn featuring_in_bulk_reports_each_id() {
+        for id in [1, 2] {
+            do_insert(&Shoe {
+                id,
+                ..Default::default()
+            });
+        }
+        let results = _set_featured_bulk(vec![1, 3, 2], true);
+        assert!(matches!(results[0], Ok(1)));
+        assert!(matches!(results[1], Err(Error::NotFound { .. })));
+        assert!(matches!(results[2], Ok(2)));
+        assert_eq!(ids_of(&get_featured_shoes()), vec![1, 2]);
+
+        assert!(_set_featured_bulk(vec![2], false)[0].is_ok());
+        assert_eq!(ids_of(&get_featured_shoes()), vec![1]);
+    }
+
+    #[test]
+    fn underperformers_are_pricey_shoes_with_few_likes() {
+        let owner = Principal::from_slice(&[1]);
+        for (id, price, like, deleted_at) in [
+            (1, 200, 1, None),
+            (2, 50, 0, None),
+            (3, 300, 9, None),
+            (4, 250, 0, Some(1)),
+            (5, 100, 2, None),
+        ] {
+            do_insert(&Shoe {
+                id,
+                owner: owner.to_string(),
+                price,
+                like,
+                deleted_at,
+                ..Default::default()
+            });
+        }
+        do_insert(&Shoe {
+            id: 6,
+            owner: Principal::from_slice(&[2]).to_string(),
+            price: 200,
+            ..Default::default()
+        });
+        assert_eq!(
+            ids_of(&_underperformers_of(&owner.to_string(), 100, 2)),
+            vec![1, 5]
+        );
+    }
+
+    #[test]
+    fn timestamp_converts_between_units() {
+        let timestamp = Timestamp(1_234_567_890_123);
+        assert_eq!(timestamp.as_nanos(), 1_234_567_890_123);
+        assert_eq!(timestamp.as_millis(), 1_234_567);
+        assert_eq!(timestamp.as_secs(), 1_234);
+        assert_eq!(timestamp_to_millis(timestamp), 1_234_567);
+        assert_eq!(timestamp_to_secs(timestamp), 1_234);
+    }
+
+    #[test]
+    fn timestamp_encodes_as_a_plain_nat64() {
+        let bytes = Encode!(&Timestamp(42)).unwrap();
+        assert_eq!(Decode!(&bytes, u64).unwrap(), 42);
+        let bytes = Encode!(&42u64).unwrap();
+        assert_eq!(Decode!(&bytes, Timestamp).unwrap().as_nanos(), 42);
+    }
+}